@@ -1,12 +1,16 @@
 extern crate alloc;
 
-use crate::expression::FnBody;
+use crate::builtins::seq;
+use crate::destructure;
+use crate::expression::{FnBody, Lambda};
 use crate::Environment;
-use crate::Error;
 use crate::Expression;
-use crate::{
-    make_builtin__a_and_b, make_builtin__a_b_and_c, make_builtin__args, make_builtin__x_and_ys,
-};
+use crate::{make_builtin__a_and_b, make_builtin__args, make_builtin__x, make_builtin__x_and_ys};
+use crate::{Arity, Error, ErrorKind};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 
@@ -18,15 +22,158 @@ make_builtin__a_and_b!(DEF, |env: &mut Environment,
         env.define_var(name.as_str(), data)?;
         Ok(Expression::Nil)
     } else {
-        Err(Error::ExpectedSymbol)
+        Err(Error::new(ErrorKind::ExpectedSymbol))
     }
 });
 
+// Build a lambda from a vector of parameter patterns and the body forms. The
+// parameter vector is validated here, so that malformed parameter lists are
+// reported when the function is defined, not when it is called. The lambda
+// captures the local bindings currently visible in `env`.
+fn make_lambda(
+    env: &Environment,
+    name: Option<String>,
+    params: Expression,
+    body: Vec<Expression>,
+) -> Result<Lambda, Error> {
+    // Args matched by a destructuring pattern are first bound to a generated
+    // name, and destructured from there. The name contains a delimiter, so the
+    // reader can never produce it as a symbol.
+    let mut patterns = Vec::new();
+    let params: Vec<_> = match params {
+        Expression::Vector(params) => params
+            .into_iter()
+            .map(|p| match p {
+                Expression::Symbol(s) => Ok::<_, Error>(s),
+                pattern => {
+                    destructure::check(&pattern)?;
+                    let name = format!("#({})", patterns.len());
+                    patterns.push((name.clone(), pattern));
+                    Ok(name)
+                }
+            })
+            .try_collect()?,
+        _ => return Err(Error::new(ErrorKind::ExpectedVector)),
+    };
+
+    // At most one name may follow `&`
+    if let Some(idx) = params.iter().position(|p| p == "&") {
+        if params.len() > idx + 2 {
+            return Err(Error::new(ErrorKind::UnexpectedSymbol));
+        }
+    }
+
+    Ok(Lambda {
+        name,
+        params: params.join(" "),
+        patterns,
+        body,
+        env: env.capture(),
+    })
+}
+
+pub const FN: (&str, FnBody) = (
+    "params & body",
+    FnBody(|env: &mut Environment| {
+        let params = env.pop_stack_if_named("params")?;
+        let body = match env.pop_stack_if_named("body") {
+            Ok(body) => body.try_into()?,
+            Err(_) => Vec::new(),
+        };
+        Ok(Expression::Lambda(Rc::new(make_lambda(
+            env, None, params, body,
+        )?)))
+    }),
+);
+
+pub const DEFN: (&str, FnBody) = (
+    "name params & body",
+    FnBody(|env: &mut Environment| {
+        let name = env.pop_stack_if_named("name")?;
+        let params = env.pop_stack_if_named("params")?;
+        let body = match env.pop_stack_if_named("body") {
+            Ok(body) => body.try_into()?,
+            Err(_) => Vec::new(),
+        };
+        if let Expression::Symbol(name) = name {
+            let lambda = make_lambda(env, Some(name.clone()), params, body)?;
+            env.define_var(name.as_str(), Expression::Lambda(Rc::new(lambda)))?;
+            Ok(Expression::Nil)
+        } else {
+            Err(Error::new(ErrorKind::ExpectedSymbol))
+        }
+    }),
+);
+
+pub const DEFMACRO: (&str, FnBody) = (
+    "name params & body",
+    FnBody(|env: &mut Environment| {
+        let name = env.pop_stack_if_named("name")?;
+        let params = env.pop_stack_if_named("params")?;
+        let body = match env.pop_stack_if_named("body") {
+            Ok(body) => body.try_into()?,
+            Err(_) => Vec::new(),
+        };
+        if let Expression::Symbol(name) = name {
+            let lambda = make_lambda(env, Some(name.clone()), params, body)?;
+            env.define_var(name.as_str(), Expression::Macro(Rc::new(lambda)))?;
+            Ok(Expression::Nil)
+        } else {
+            Err(Error::new(ErrorKind::ExpectedSymbol))
+        }
+    }),
+);
+
+make_builtin__x!(MACROEXPAND_1, |env: &mut Environment, x: Expression| {
+    let form = env.eval(x)?;
+    Ok(env.macroexpand_1(&form)?.unwrap_or(form))
+});
+
+make_builtin__x!(MACROEXPAND, |env: &mut Environment, x: Expression| {
+    let mut form = env.eval(x)?;
+    while let Some(expanded) = env.macroexpand_1(&form)? {
+        form = expanded;
+    }
+    Ok(form)
+});
+
+// `(gensym)` or `(gensym prefix)`, where the prefix is a string or symbol and
+// defaults to `G`. Produces a symbol which no other call will produce.
+make_builtin__args!(
+    GENSYM,
+    |env: &mut Environment| { Ok(env.gensym("G")) },
+    |env: &mut Environment, mut args: Vec<_>| {
+        if args.len() > 1 {
+            return Err(Error::new(ErrorKind::TooManyArgs));
+        }
+        match env.eval(args.remove(0))? {
+            Expression::Symbol(prefix) | Expression::String(prefix) => Ok(env.gensym(&prefix)),
+            prefix => Err(Error::new(ErrorKind::ExpectedSymbol).with_value(&prefix)),
+        }
+    }
+);
+
+// Check that a binding vector, such as the one given to `let`, holds pairs of
+// binding patterns and expressions, and collect those pairs.
+fn binding_pairs(bindings: Vec<Expression>) -> Result<Vec<(Expression, Expression)>, Error> {
+    if !bindings.len().is_multiple_of(2) {
+        return Err(Error::new(ErrorKind::UnbalancedBindings));
+    }
+    bindings
+        .as_slice()
+        .chunks(2)
+        .map(|s| {
+            destructure::check(&s[0])?;
+            Ok((s[0].clone(), s[1].clone()))
+        })
+        .try_collect()
+}
+
 pub const LET: (&str, FnBody) = (
     "bindings & exprs",
     FnBody(|env: &mut Environment| {
         if let Ok(bindings) = env.pop_stack_if_named("bindings") {
-            let bindings: Vec<_> = bindings.try_into()?;
+            let bindings = binding_pairs(bindings.try_into()?)?;
 
             // Go ahead and pop expressions to clear the stack, before we begin pushing
             // variables onto the stack. We don't evaluate expressions until after all
@@ -34,72 +181,344 @@ pub const LET: (&str, FnBody) = (
             let exprs = env.pop_stack_if_named("exprs");
 
             // Push data into lexical scope for each binding given
-            if bindings.len() % 2 != 0 {
-                return Err(Error::UnbalancedBindings);
-            } else {
-                bindings
-                    .as_slice()
-                    .windows(2)
-                    .step_by(2)
-                    .map(|s| (s[0].clone(), s[1].clone()))
-                    .try_for_each(|(name, expr)| {
-                        if let Expression::Symbol(name) = name {
-                            let value = env.eval(expr)?;
-                            env.push_stack(name.as_str(), value)
-                        } else {
-                            Err(Error::ExpectedSymbol)
-                        }
-                    })?;
-            }
+            bindings.into_iter().try_for_each(|(pattern, expr)| {
+                let value = env.eval(expr)?;
+                destructure::bind(env, &pattern, value)
+            })?;
 
             // Optionally evaluate the expressions, returning the result of the final
             // evaluation.
             if let Ok(exprs) = exprs {
-                let exprs: Vec<Expression> = exprs.try_into()?;
-                let mut res = Expression::Nil;
-                for expr in exprs.into_iter().rev() {
-                    res = env.eval(expr)?;
-                }
-                Ok(res)
+                env.eval_body(exprs.try_into()?)
             } else {
                 Ok(Expression::Nil)
             }
         } else {
-            Err(Error::UnexpectedArgs)
+            Err(Error::new(ErrorKind::UnexpectedArgs))
+        }
+    }),
+);
+
+pub const LOOP: (&str, FnBody) = (
+    "bindings & exprs",
+    FnBody(|env: &mut Environment| {
+        if let Ok(bindings) = env.pop_stack_if_named("bindings") {
+            let bindings = binding_pairs(bindings.try_into()?)?;
+            let exprs = match env.pop_stack_if_named("exprs") {
+                Ok(exprs) => exprs.try_into()?,
+                Err(_) => Vec::new(),
+            };
+
+            // Bind the initial values, as `let` does
+            let mut patterns = Vec::new();
+            for (pattern, expr) in bindings {
+                let value = env.eval(expr)?;
+                destructure::bind(env, &pattern, value)?;
+                patterns.push(pattern);
+            }
+            env.eval_loop(&patterns, exprs)
+        } else {
+            Err(Error::new(ErrorKind::UnexpectedArgs))
         }
     }),
 );
 
-make_builtin__a_b_and_c!(IF, |env: &mut Environment,
-                              a: Expression,
-                              b: Expression,
-                              c: Expression| {
-    // Only Nil & `false` are logically false. All other values, including the
-    // number `0` are considered true.
-    match env.eval(a)? {
-        Expression::Nil | Expression::Bool(false) => env.eval(c),
-        _ => env.eval(b),
+make_builtin__args!(
+    RECUR,
+    |env: &mut Environment| { env.recur(Vec::new()) },
+    |env: &mut Environment, args: Vec<_>| {
+        let vals = args.into_iter().map(|e| env.eval(e)).try_collect()?;
+        env.recur(vals)
+    }
+);
+
+// Quoted forms are plain data, so any spans they were read with are removed.
+make_builtin__x!(QUOTE, |_env: &mut Environment, x: Expression| Ok(
+    x.strip_spans()
+));
+
+// If `form` is a list of the form `(name x)`, return `x`.
+fn unwrap_form(form: &Expression, name: &str) -> Option<Expression> {
+    match form {
+        Expression::List(items) if items.len() == 2 => match &items[0] {
+            Expression::Symbol(s) if s == name => Some(items[1].clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Build the call `(name args...)`.
+fn call_form(name: &str, mut args: Vec<Expression>) -> Expression {
+    args.insert(0, Expression::Symbol(name.to_string()));
+    Expression::List(args)
+}
+
+// Expand a quasiquoted template into the code that constructs it. Symbols are
+// quoted, `~x` is replaced by `x`, and `~@x` splices the items of `x` into the
+// enclosing list, vector or set. Lists and vectors are built with `list` and
+// `vector`, or with `concat` when something is spliced into them. Sets are
+// built with `set`, and maps with `into` from their entries. Nothing may be
+// spliced into a map, as its entries are pairs rather than items.
+fn expand_quasiquote(form: Expression) -> Result<Expression, Error> {
+    if let Some(x) = unwrap_form(&form, "unquote") {
+        return Ok(x);
+    } else if unwrap_form(&form, "unquote-splicing").is_some() {
+        // There is no list or vector to splice into
+        return Err(Error::new(ErrorKind::UnexpectedSymbol));
+    }
+
+    match form {
+        Expression::List(items) => expand_quasiquote_items("list", items),
+        Expression::Vector(items) => expand_quasiquote_items("vector", items),
+        Expression::Set(items) => Ok(call_form(
+            "set",
+            vec![expand_quasiquote_items(
+                "list",
+                items.into_iter().collect(),
+            )?],
+        )),
+        Expression::Map(entries) => {
+            let entries = entries
+                .into_iter()
+                .map(|(k, v)| {
+                    let entry = vec![expand_quasiquote(k)?, expand_quasiquote(v)?];
+                    Ok::<_, Error>(call_form("vector", entry))
+                })
+                .try_collect()?;
+            Ok(call_form(
+                "into",
+                vec![Expression::Map(BTreeMap::new()), call_form("list", entries)],
+            ))
+        }
+        Expression::Symbol(_) => Ok(call_form("quote", vec![form])),
+        _ => Ok(form),
+    }
+}
+
+fn expand_quasiquote_items(ctor: &str, items: Vec<Expression>) -> Result<Expression, Error> {
+    if items
+        .iter()
+        .any(|item| unwrap_form(item, "unquote-splicing").is_some())
+    {
+        let parts = items
+            .into_iter()
+            .map(|item| match unwrap_form(&item, "unquote-splicing") {
+                Some(x) => Ok::<_, Error>(x),
+                None => Ok(call_form("list", vec![expand_quasiquote(item)?])),
+            })
+            .try_collect()?;
+        match ctor {
+            "vector" => Ok(call_form("vec", vec![call_form("concat", parts)])),
+            _ => Ok(call_form("concat", parts)),
+        }
+    } else {
+        let items = items.into_iter().map(expand_quasiquote).try_collect()?;
+        Ok(call_form(ctor, items))
     }
+}
+
+// `(quasiquote x)`, usually written `` `x ``, produces `x` as data, as `quote`
+// does, except that the forms marked by `~` and `~@` within it are evaluated.
+// Nested quasiquotes are not handled specially: their unquoted forms are
+// evaluated by the outermost quasiquote, rather than left for the inner one.
+make_builtin__x!(QUASIQUOTE, |env: &mut Environment, x: Expression| {
+    env.eval_tail(expand_quasiquote(x.strip_spans())?)
 });
 
+// Pop the optional else branch of a conditional, which is absent if not given.
+fn pop_else(env: &mut Environment, name: &str, arity: Arity) -> Result<Expression, Error> {
+    let mut branches: Vec<_> = match env.pop_stack_if_named("else") {
+        Ok(branches) => branches.try_into()?,
+        Err(_) => Vec::new(),
+    };
+    if branches.len() > 1 {
+        return Err(Error::new(ErrorKind::TooManyArgs)
+            .with_function(name)
+            .with_arity(arity, arity.min + branches.len()));
+    }
+    Ok(branches.pop().unwrap_or(Expression::Nil))
+}
+
+// `(if test then else)`, where the else branch may be left out, producing
+// `nil`.
+pub const IF: (&str, FnBody) = (
+    "test then & else",
+    FnBody(|env: &mut Environment| {
+        let test = env.pop_stack_if_named("test")?;
+        let then = env.pop_stack_if_named("then")?;
+        let arity = Arity {
+            min: 2,
+            max: Some(3),
+        };
+        let otherwise = pop_else(env, "if", arity)?;
+        if env.eval(test)?.is_truthy() {
+            env.eval_tail(then)
+        } else {
+            env.eval_tail(otherwise)
+        }
+    }),
+);
+
+make_builtin__x_and_ys!(
+    WHEN,
+    |env: &mut Environment, x: Expression| {
+        env.eval(x)?;
+        Ok(Expression::Nil)
+    },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| {
+        if env.eval(x)?.is_truthy() {
+            env.eval_body(ys)
+        } else {
+            Ok(Expression::Nil)
+        }
+    }
+);
+
+make_builtin__x_and_ys!(
+    WHEN_NOT,
+    |env: &mut Environment, x: Expression| {
+        env.eval(x)?;
+        Ok(Expression::Nil)
+    },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| {
+        if env.eval(x)?.is_truthy() {
+            Ok(Expression::Nil)
+        } else {
+            env.eval_body(ys)
+        }
+    }
+);
+
+// `(cond test expr ...)` evaluates the expression following the first test
+// which is true, or produces `nil` if none are. Any keyword is true, so `:else`
+// may be used as a final catch-all test.
 make_builtin__args!(
-    DO,
+    COND,
     |_env: &mut Environment| { Ok(Expression::Nil) },
-    |env: &mut Environment, args: Vec<_>| {
-        let mut res = Expression::Nil;
-        for expr in args.into_iter().rev() {
-            res = env.eval(expr)?;
+    |env: &mut Environment, args: Vec<Expression>| {
+        if !args.len().is_multiple_of(2) {
+            return Err(Error::new(ErrorKind::UnexpectedArgs)
+                .with_function("cond")
+                .with_message("Each test must be followed by an expression."));
+        }
+        let mut args = args.into_iter();
+        while let (Some(test), Some(expr)) = (args.next(), args.next()) {
+            if env.eval(test)?.is_truthy() {
+                return env.eval_tail(expr);
+            }
+        }
+        Ok(Expression::Nil)
+    }
+);
+
+// `(case expr constant result ... default)` evaluates the result following the
+// constant equal to the value of `expr`, or else the default, if given. The
+// constants are not evaluated, and a list of constants matches any one of them.
+make_builtin__x_and_ys!(
+    CASE,
+    |env: &mut Environment, x: Expression| {
+        let value = env.eval(x)?;
+        Err(Error::new(ErrorKind::DataNotFound)
+            .with_function("case")
+            .with_message("No clause matches the value.")
+            .with_value(&value))
+    },
+    |env: &mut Environment, x: Expression, ys: Vec<Expression>| {
+        let value = env.eval(x)?;
+        let mut clauses = ys.into_iter();
+        while let Some(constant) = clauses.next() {
+            let result = match clauses.next() {
+                Some(result) => result,
+                None => return env.eval_tail(constant),
+            };
+            let matches = match constant.strip_spans() {
+                Expression::List(constants) => constants.contains(&value),
+                constant => constant == value,
+            };
+            if matches {
+                return env.eval_tail(result);
+            }
+        }
+        Err(Error::new(ErrorKind::DataNotFound)
+            .with_function("case")
+            .with_message("No clause matches the value.")
+            .with_value(&value))
+    }
+);
+
+// Evaluate the single binding of an `if-let` or `when-let`. If its value is
+// true, the value is bound to the binding's pattern.
+fn bind_if_true(env: &mut Environment, name: &str, bindings: Expression) -> Result<bool, Error> {
+    let mut bindings = binding_pairs(
+        bindings
+            .try_into()
+            .or(Err(Error::new(ErrorKind::ExpectedVector)))?,
+    )?;
+    let (pattern, expr) = match (bindings.pop(), bindings.is_empty()) {
+        (Some(binding), true) => binding,
+        _ => {
+            return Err(Error::new(ErrorKind::UnbalancedBindings)
+                .with_function(name)
+                .with_message("Exactly one binding is allowed."))
+        }
+    };
+    let value = env.eval(expr)?;
+    if value.is_truthy() {
+        destructure::bind(env, &pattern, value)?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+// `(if-let [pattern expr] then else)` binds the value of `expr` for the then
+// branch if it is true. The else branch may be left out, producing `nil`.
+pub const IF_LET: (&str, FnBody) = (
+    "bindings then & else",
+    FnBody(|env: &mut Environment| {
+        let bindings = env.pop_stack_if_named("bindings")?;
+        let then = env.pop_stack_if_named("then")?;
+        let arity = Arity {
+            min: 2,
+            max: Some(3),
+        };
+        let otherwise = pop_else(env, "if-let", arity)?;
+        if bind_if_true(env, "if-let", bindings)? {
+            env.eval_tail(then)
+        } else {
+            env.eval_tail(otherwise)
+        }
+    }),
+);
+
+make_builtin__x_and_ys!(
+    WHEN_LET,
+    |env: &mut Environment, x: Expression| {
+        bind_if_true(env, "when-let", x)?;
+        Ok(Expression::Nil)
+    },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| {
+        if bind_if_true(env, "when-let", x)? {
+            env.eval_body(ys)
+        } else {
+            Ok(Expression::Nil)
         }
-        Ok(res)
     }
 );
 
+make_builtin__args!(
+    DO,
+    |_env: &mut Environment| { Ok(Expression::Nil) },
+    |env: &mut Environment, args: Vec<_>| { env.eval_body(args) }
+);
+
 make_builtin__x_and_ys!(
     WHILE,
     |_env: &mut Environment, _| { Ok(Expression::Nil) },
     |env: &mut Environment, test_expr: Expression, body_exprs: Vec<Expression>| {
         while env.eval(test_expr.clone())?.try_into()? {
-            for expr in body_exprs.iter().rev() {
+            for expr in body_exprs.iter() {
                 env.eval(expr.clone())?;
             }
         }
@@ -111,79 +530,57 @@ pub const DOSEQ: (&str, FnBody) = (
     "bindings & exprs",
     FnBody(|env: &mut Environment| {
         if let Ok(bindings) = env.pop_stack_if_named("bindings") {
-            let bindings: Vec<_> = bindings.try_into()?;
+            let bindings = binding_pairs(bindings.try_into()?)?;
 
             // Go ahead and pop expressions to clear the stack, before we begin pushing
             // variables onto the stack. We don't evaluate expressions until after all
             // variables have been pushed to the stack, though.
             let exprs = env.pop_stack_if_named("exprs");
 
-            // Push data into lexical scope for each binding given
-            if bindings.len() % 2 != 0 {
-                return Err(Error::UnbalancedBindings);
-            }
-
-            // Collect bindings into an iterator of tuples, e.g:
-            // [(x, [...]), (y, [...]), ...]
-            let binds = bindings
-                .as_slice()
-                .windows(2)
-                .step_by(2)
-                .map(|s| match (&s[0], &s[1]) {
-                    (Expression::Symbol(var), Expression::Vector(vals)) => Ok((var, vals)),
-                    _ => Err(Error::TypeMismatch),
-                });
+            // Each pattern is bound to the items of a collection in turn. The
+            // collections are evaluated before anything is bound.
+            let binds: Vec<(Expression, Vec<Expression>)> = bindings
+                .into_iter()
+                .map(|(pattern, coll)| Ok::<_, Error>((pattern, seq::items(env.eval(coll)?)?)))
+                .try_collect()?;
 
             // Determine the number of loop iterations, & check that each var has the same
             // number of vals to iterate
-            let loop_count = binds
-                .clone()
-                .map(|bind| match bind {
-                    Ok((_, val)) => Ok(val.len()),
-                    Err(e) => Err(e),
-                })
-                .try_fold(None, |acc, vlen| {
-                    let len = vlen?;
-                    match (acc, len) {
-                        (None, _) => Ok(Some(len)),
-                        (Some(acc), len) => match acc == len {
-                            true => Ok(Some(acc)),
-                            false => Err(Error::UnbalancedBindings),
-                        },
-                    }
-                })?
-                .unwrap_or(0);
+            let loop_count = binds.first().map_or(0, |(_, vals)| vals.len());
+            if binds.iter().any(|(_, vals)| vals.len() != loop_count) {
+                return Err(Error::new(ErrorKind::UnbalancedBindings));
+            }
 
             // Iterate over all bindings
             for i in 0..loop_count {
                 // Update each binding for this loop iteration
-                binds.clone().try_for_each(|bind| {
-                    let (var, vals) = bind?;
-                    let val = env.eval(vals[i].clone())?;
-                    env.push_stack(var, val)
-                })?;
+                for (pattern, vals) in &binds {
+                    destructure::bind(env, pattern, vals[i].clone())?;
+                }
 
                 // Optionally evaluate the expressions
                 if let Ok(exprs) = exprs.clone() {
                     let exprs: Vec<Expression> = exprs.try_into()?;
-                    for expr in exprs.into_iter().rev() {
+                    for expr in exprs.into_iter() {
                         env.eval(expr)?;
                     }
                 }
             }
             Ok(Expression::Nil)
         } else {
-            Err(Error::UnexpectedArgs)
+            Err(Error::new(ErrorKind::UnexpectedArgs))
         }
     }),
 );
 
+// `(dotimes [n count] body)`. The binding vector is not evaluated, as a vector
+// literal would be, because `n` names the var to bind; only `count` is.
 make_builtin__a_and_b!(DOTIMES, |env: &mut Environment,
                                  binds: Expression,
                                  body: Expression| {
-    let binds: Vec<_> = env.eval(binds)?.try_into()?;
+    let binds: Vec<_> = binds.try_into()?;
     if 2 != binds.len() {
-        Err(Error::UnbalancedBindings)
+        Err(Error::new(ErrorKind::UnbalancedBindings))
     } else if let Expression::Symbol(var) = &binds[0] {
         let range = env.eval(binds[1].clone())?.try_into()?;
         for i in 0..range {
@@ -192,7 +589,7 @@ make_builtin__a_and_b!(DOTIMES, |env: &mut Environment,
         }
         Ok(Expression::Nil)
     } else {
-        Err(Error::ExpectedSymbol)
+        Err(Error::new(ErrorKind::ExpectedSymbol))
     }
 });
 
@@ -201,27 +598,101 @@ make_builtin__args!(
     |_env: &mut Environment| { Ok(Expression::Vector(vec![])) },
     |env: &mut Environment, args: Vec<_>| {
         Ok(Expression::Vector(
-            args.into_iter().rev().map(|e| env.eval(e)).try_collect()?,
+            args.into_iter().map(|e| env.eval(e)).try_collect()?,
+        ))
+    }
+);
+
+make_builtin__args!(
+    LIST,
+    |_env: &mut Environment| { Ok(Expression::List(vec![])) },
+    |env: &mut Environment, args: Vec<_>| {
+        Ok(Expression::List(
+            args.into_iter().map(|e| env.eval(e)).try_collect()?,
         ))
     }
 );
 
+make_builtin__x!(VEC, |env: &mut Environment, x: Expression| {
+    match env.eval(x)? {
+        Expression::Nil => Ok(Expression::Vector(vec![])),
+        Expression::List(v) | Expression::Vector(v) => Ok(Expression::Vector(v)),
+        Expression::Set(s) => Ok(Expression::Vector(s.into_iter().collect())),
+        _ => Err(Error::new(ErrorKind::TypeMismatch)),
+    }
+});
+
+make_builtin__args!(
+    CONCAT,
+    |_env: &mut Environment| { Ok(Expression::List(vec![])) },
+    |env: &mut Environment, args: Vec<_>| {
+        let mut items = Vec::new();
+        for arg in args {
+            match env.eval(arg)? {
+                Expression::Nil => {}
+                Expression::List(mut v) | Expression::Vector(mut v) => items.append(&mut v),
+                _ => return Err(Error::new(ErrorKind::TypeMismatch)),
+            }
+        }
+        Ok(Expression::List(items))
+    }
+);
+
+// Add an item to a collection, wherever is most natural for its type: vectors
+// grow at the end, lists at the front, and sets and maps (given `[k v]` pairs)
+// are unordered. Adding to `nil` produces a list.
+pub(crate) fn conj(coll: &mut Expression, item: Expression) -> Result<(), Error> {
+    if coll.is_nil() {
+        *coll = Expression::List(vec![]);
+    }
+    match (coll, item) {
+        (Expression::Vector(v), item) => v.push(item),
+        (Expression::List(v), item) => v.insert(0, item),
+        (Expression::Set(s), item) => {
+            s.insert(item);
+        }
+        (Expression::Map(m), Expression::Vector(mut pair)) if pair.len() == 2 => {
+            let v = pair.pop().unwrap();
+            m.insert(pair.pop().unwrap(), v);
+        }
+        (coll, _) => return Err(Error::new(ErrorKind::TypeMismatch).with_value(coll)),
+    }
+    Ok(())
+}
+
+make_builtin__x_and_ys!(
+    CONJ,
+    |env: &mut Environment, x: Expression| { env.eval(x) },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| {
+        let mut coll = env.eval(x)?;
+        for y in ys {
+            let y = env.eval(y)?;
+            conj(&mut coll, y)?;
+        }
+        Ok(coll)
+    }
+);
+
+// `(nth coll idx)` or `(nth coll idx default)`. The collection and index are
+// evaluated, so either may be any expression. The default, `nil` if not given,
+// is only evaluated if the index is out of range.
 pub const NTH: (&str, FnBody) = (
     "vec & args",
     FnBody(|env: &mut Environment| {
+        let vec = env.pop_stack_if_named("vec")?;
         let vec: Vec<_> = env
-            .pop_stack_if_named("vec")?
+            .eval(vec)?
             .try_into()
-            .or(Err(Error::ExpectedVector))?;
+            .or(Err(Error::new(ErrorKind::ExpectedVector)))?;
         let args: Vec<_> = env
             .pop_stack_if_named("args")
-            .or(Err(Error::TooFewArgs))?
+            .or(Err(Error::new(ErrorKind::TooFewArgs)))?
             .try_into()?;
         let (idx, dnf) = match args.len() {
-            0 => Err(Error::TooFewArgs),
+            0 => Err(Error::new(ErrorKind::TooFewArgs)),
             1 => Ok((env.eval(args[0].clone())?, Expression::Nil)),
-            2 => Ok((env.eval(args[1].clone())?, args[0].clone())),
-            _ => Err(Error::TooManyArgs),
+            2 => Ok((env.eval(args[0].clone())?, args[1].clone())),
+            _ => Err(Error::new(ErrorKind::TooManyArgs)),
         }?;
         match idx {
             Expression::Number(idx) => {
@@ -231,35 +702,41 @@ pub const NTH: (&str, FnBody) = (
                     env.eval(dnf)
                 }
             }
-            _ => Err(Error::TypeMismatch),
+            _ => Err(Error::new(ErrorKind::TypeMismatch)),
         }
     }),
 );
 
+// The last item of a vector. The argument is evaluated, so it may be any
+// expression producing a vector.
 pub const PEEK: (&str, FnBody) = (
     "vec",
     FnBody(|env: &mut Environment| {
+        let vec = env.pop_stack_if_named("vec")?;
         let vec: Vec<_> = env
-            .pop_stack_if_named("vec")?
+            .eval(vec)?
             .try_into()
-            .or(Err(Error::ExpectedVector))?;
+            .or(Err(Error::new(ErrorKind::ExpectedVector)))?;
         match vec.last() {
             Some(expr) => Ok(expr.clone()),
-            None => Err(Error::Empty),
+            None => Err(Error::new(ErrorKind::Empty)),
         }
     }),
 );
 
+// A vector without its last item. The argument is evaluated, so it may be any
+// expression producing a vector.
 pub const POP: (&str, FnBody) = (
     "vec",
     FnBody(|env: &mut Environment| {
+        let vec = env.pop_stack_if_named("vec")?;
         let mut vec: Vec<_> = env
-            .pop_stack_if_named("vec")?
+            .eval(vec)?
             .try_into()
-            .or(Err(Error::ExpectedVector))?;
+            .or(Err(Error::new(ErrorKind::ExpectedVector)))?;
         match vec.pop() {
             Some(_) => Ok(Expression::Vector(vec)),
-            None => Err(Error::Empty),
+            None => Err(Error::new(ErrorKind::Empty)),
         }
     }),
 );
@@ -267,8 +744,9 @@ pub const POP: (&str, FnBody) = (
 #[cfg(test)]
 mod tests {
     use crate::Environment;
-    use crate::Error;
+    use crate::ErrorKind;
     use crate::Expression;
+    use alloc::string::ToString;
     use alloc::vec;
     use alloc::vec::Vec;
 
@@ -276,7 +754,10 @@ mod tests {
     fn def() {
         let mut env = Environment::new();
         env.load_default_builtins().unwrap();
-        assert_eq!(env.parse_eval("(def)"), Err(Error::TooFewArgs));
+        assert_eq!(
+            env.parse_eval("(def)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
         assert_eq!(env.parse_eval("(def myVar 2)"), Ok(Expression::Nil));
         assert_eq!(env.parse_eval("(+ myVar 9)"), Ok(Expression::Number(11)));
         assert_eq!(env.parse_eval("(def myVar 8)"), Ok(Expression::Nil));
@@ -289,148 +770,973 @@ mod tests {
     }
 
     #[test]
-    fn op_let() {
+    fn op_fn() {
         let mut env = Environment::new();
         env.load_default_builtins().unwrap();
-        assert_eq!(env.parse_eval("(let)"), Err(Error::TooFewArgs));
-        assert_eq!(env.parse_eval("(let [a])"), Err(Error::UnbalancedBindings));
-        assert_eq!(env.parse_eval("(let [myVar 2])"), Ok(Expression::Nil));
         assert_eq!(
-            env.parse_eval("(let [myVar 2] (+ myVar 1))"),
-            Ok(Expression::Number(3))
+            env.parse_eval("(fn)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
         );
         assert_eq!(
-            env.parse_eval("(let [myVar (inc 2)] (+ myVar 1))"),
-            Ok(Expression::Number(4))
+            env.parse_eval("(fn x 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedVector)
         );
         assert_eq!(
-            env.parse_eval("(let [a 1 b 2] (+ a b))"),
+            env.parse_eval("(fn [1] 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedSymbol)
+        );
+        assert_eq!(
+            env.parse_eval("(fn [a & b c] a)").map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedSymbol)
+        );
+        assert!(env.parse_eval("(fn [a] a)").unwrap().is_function());
+        assert_eq!(env.parse_eval("((fn [] 5))"), Ok(Expression::Number(5)));
+        assert_eq!(env.parse_eval("((fn [a]) 5)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("((fn [a b] (+ a b)) 1 (inc 1))"),
             Ok(Expression::Number(3))
         );
         assert_eq!(
-            env.parse_eval("(let [a 1 b 2] (+ a b) (inc a))"),
-            Ok(Expression::Number(2))
+            env.parse_eval("((fn [a & more] more) 1 2 3)"),
+            env.parse_eval("(vector 2 3)")
+        );
+        assert_eq!(
+            env.parse_eval("((fn [a & more] more) 1)"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(
+            env.parse_eval("((fn [a b] a) 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("((fn [a b] a) 1 2 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(5 1 2)").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedFunction)
         );
     }
 
     #[test]
-    fn op_if() {
+    fn defn() {
         let mut env = Environment::new();
         env.load_default_builtins().unwrap();
-        assert_eq!(env.parse_eval("(if)"), Err(Error::TooFewArgs));
         assert_eq!(
-            env.parse_eval("(if (< 10 11 12) true false)"),
-            Ok(Expression::Bool(true))
+            env.parse_eval("(defn)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
         );
         assert_eq!(
-            env.parse_eval("(if (< 10 11 10) true false)"),
-            Ok(Expression::Bool(false))
+            env.parse_eval("(defn 1 [] 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedSymbol)
         );
         assert_eq!(
-            env.parse_eval("(if (inc 10) true false)"),
-            Ok(Expression::Bool(true))
+            env.parse_eval("(defn add [a b] (+ a b))"),
+            Ok(Expression::Nil)
         );
+        assert_eq!(env.parse_eval("(add 4 5)"), Ok(Expression::Number(9)));
         assert_eq!(
-            env.parse_eval("(if true true false)"),
-            Ok(Expression::Bool(true))
+            env.parse_eval("(add 4)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
         );
         assert_eq!(
-            env.parse_eval("(if false true false)"),
-            Ok(Expression::Bool(false))
+            env.parse_eval("(defn fact [n] (if (<= n 1) 1 (* n (fact (dec n)))))"),
+            Ok(Expression::Nil)
         );
+        assert_eq!(env.parse_eval("(fact 5)"), Ok(Expression::Number(120)));
+
+        // Functions are values, and may be passed around like any other data
+        assert_eq!(env.parse_eval("(def plus add)"), Ok(Expression::Nil));
+        assert_eq!(env.parse_eval("(plus 1 2)"), Ok(Expression::Number(3)));
         assert_eq!(
-            env.parse_eval("(if nil true false)"),
-            Ok(Expression::Bool(false))
+            env.parse_eval("(defn twice [f x] (f (f x)))"),
+            Ok(Expression::Nil)
         );
+        assert_eq!(env.parse_eval("(twice inc 1)"), Ok(Expression::Number(3)));
         assert_eq!(
-            env.parse_eval("(if (dec 1) true false)"),
-            Ok(Expression::Bool(true))
+            env.parse_eval("(twice (fn [x] (* x x)) 3)"),
+            Ok(Expression::Number(81))
         );
+        assert_eq!(
+            env.parse_eval("(defn pair [a b] [a b])"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(env.parse_eval("(pair 1 2)"), env.parse_eval("(vector 1 2)"));
     }
 
     #[test]
-    fn op_do() {
+    fn lexical_scope() {
         let mut env = Environment::new();
         env.load_default_builtins().unwrap();
-        assert_eq!(env.parse_eval("(do)"), Ok(Expression::Nil));
+
+        // A function does not see the local bindings of its caller
+        assert_eq!(env.parse_eval("(defn get-x [] x)"), Ok(Expression::Nil));
         assert_eq!(
-            env.parse_eval("(do true true false)"),
-            Ok(Expression::Bool(false))
+            env.parse_eval("(let [x 5] (get-x))").map_err(|e| e.kind()),
+            Err(ErrorKind::DataNotFound)
         );
+        assert_eq!(env.parse_eval("(def x 1)"), Ok(Expression::Nil));
         assert_eq!(
-            env.parse_eval("(do (+ 1 3) (- 9 (+ 8 1)))"),
-            Ok(Expression::Number(0))
+            env.parse_eval("(let [x 5] (get-x))"),
+            Ok(Expression::Number(1))
         );
-    }
 
-    #[test]
-    fn op_while() {
-        let mut env = Environment::new();
-        env.load_default_builtins().unwrap();
-        assert_eq!(env.parse_eval("(while)"), Err(Error::TooFewArgs));
-        assert_eq!(env.parse_eval("(while false)"), Ok(Expression::Nil));
-        assert_eq!(env.parse_eval("(def myVar 8)"), Ok(Expression::Nil));
+        // Closures keep the bindings visible where they were defined, even after
+        // the defining frame is gone
         assert_eq!(
-            env.parse_eval("(do (while (> myVar 3) (def myVar (dec myVar))) myVar)"),
-            Ok(Expression::Number(3))
+            env.parse_eval("(def add5 (let [n 5] (fn [y] (+ y n))))"),
+            Ok(Expression::Nil)
         );
-    }
-
-    #[test]
-    fn doseq() {
-        let mut env = Environment::new();
-        env.load_default_builtins().unwrap();
-        assert_eq!(env.parse_eval("(doseq)"), Err(Error::TooFewArgs));
+        assert_eq!(env.parse_eval("(add5 1)"), Ok(Expression::Number(6)));
         assert_eq!(
-            env.parse_eval("(doseq [a])"),
-            Err(Error::UnbalancedBindings)
+            env.parse_eval("(defn adder [n] (fn [y] (+ y n)))"),
+            Ok(Expression::Nil)
         );
+        assert_eq!(env.parse_eval("((adder 3) 4)"), Ok(Expression::Number(7)));
         assert_eq!(
-            env.parse_eval("(doseq [a [1 2] b [9]])"),
-            Err(Error::UnbalancedBindings)
+            env.parse_eval("(let [n 100] ((adder 3) 4))"),
+            Ok(Expression::Number(7))
         );
+
+        // Parameters shadow captured bindings
         assert_eq!(
-            env.parse_eval("(doseq [a [1] b [9 8]])"),
-            Err(Error::UnbalancedBindings)
+            env.parse_eval("(let [y 1] ((fn [y] y) 2))"),
+            Ok(Expression::Number(2))
         );
-        assert_eq!(env.parse_eval("(doseq [a 1])"), Err(Error::TypeMismatch));
+
+        // Local bindings shadow builtins, whether called or passed as values
         assert_eq!(
-            env.parse_eval("(doseq [a [1 2] b [9 8]])"),
-            Ok(Expression::Nil)
+            env.parse_eval("(let [inc (fn [x] (* x 10))] [(inc 1) (map inc [1])])"),
+            env.parse_eval("(do [10 (list 10)])")
         );
-        // Define some vars
-        env.parse_eval("(do (def sum 0) (def prod 1))").unwrap();
         assert_eq!(
-            env.parse_eval(
-                "(doseq [a [1 2] b [9 8]] (def sum (+ sum a b)) (def prod (* prod a b)))"
-            ),
-            Ok(Expression::Nil)
+            env.parse_eval("(do (defn twice [count x] (count (count x))) (twice inc 1))"),
+            Ok(Expression::Number(3))
         );
-        assert_eq!(env.parse_eval("(+ sum 0)"), Ok(Expression::Number(20)));
-        assert_eq!(env.parse_eval("(+ prod 0)"), Ok(Expression::Number(144)));
+        assert_eq!(env.stack_height(), 1);
     }
 
     #[test]
-    fn dotimes() {
+    fn globals_shadow_builtins() {
         let mut env = Environment::new();
         env.load_default_builtins().unwrap();
-        assert_eq!(env.parse_eval("(dotimes)"), Err(Error::TooFewArgs));
-        assert_eq!(env.parse_eval("(dotimes [n 5])"), Err(Error::TooFewArgs));
+        env.parse_eval("(def count 5)").unwrap();
+        assert_eq!(env.parse_eval("(do count)"), Ok(Expression::Number(5)));
+        assert_eq!(env.parse_eval("(+ count 1)"), Ok(Expression::Number(6)));
+
+        env.parse_eval("(defn first [x] :mine)").unwrap();
         assert_eq!(
-            env.parse_eval("(dotimes true (+ n 10))"),
-            Err(Error::ImpossibleConversion)
+            env.parse_eval("(do [(first [1 2]) (map first [[1]])])"),
+            env.parse_eval("(do [:mine (list :mine)])")
         );
-        // Define some vars
+
+        // Local bindings still shadow globals
+        assert_eq!(
+            env.parse_eval("(let [first (fn [x] 0)] (first [1]))"),
+            Ok(Expression::Number(0))
+        );
+    }
+
+    #[test]
+    fn tail_calls() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(defn countdown [n] (if (== n 0) true (countdown (dec n))))"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(
+            env.parse_eval("(countdown 1000000)"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(env.stack_height(), 1);
+
+        // Tail position extends through the last form of `do` and `let`
+        assert_eq!(
+            env.parse_eval(
+                "(defn countdown [n] (do (let [m (dec n)] (if (< m 0) true (countdown m)))))"
+            ),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(
+            env.parse_eval("(countdown 100000)"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(env.stack_height(), 1);
+
+        // Mutual recursion
+        assert_eq!(
+            env.parse_eval("(defn is-even [n] (if (== n 0) true (is-odd (dec n))))"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(
+            env.parse_eval("(defn is-odd [n] (if (== n 0) false (is-even (dec n))))"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(
+            env.parse_eval("(is-even 10001)"),
+            Ok(Expression::Bool(false))
+        );
+
+        // Frames are popped when an error unwinds
+        assert_eq!(
+            env.parse_eval("(defn fail [n] (if (== n 0) (nth) (fail (dec n))))"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(
+            env.parse_eval("(fail 1000)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(env.stack_height(), 1);
+    }
+
+    #[test]
+    fn defmacro() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(defmacro)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(defmacro 1 [] 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedSymbol)
+        );
+        assert_eq!(
+            env.parse_eval("(defmacro unless [c a b] `(if ~c ~b ~a))"),
+            Ok(Expression::Nil)
+        );
+        assert!(env.parse_eval("(do unless)").unwrap().is_macro());
+        assert_eq!(
+            env.parse_eval("(unless false 1 2)"),
+            Ok(Expression::Number(1))
+        );
+        assert_eq!(
+            env.parse_eval("(unless true 1 2)"),
+            Ok(Expression::Number(2))
+        );
+
+        // Macro args are not evaluated
+        assert_eq!(
+            env.parse_eval("(unless true (nth) 2)"),
+            Ok(Expression::Number(2))
+        );
+        assert_eq!(
+            env.parse_eval("(defmacro my-when [c & body] `(if ~c (do ~@body) nil))"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(
+            env.parse_eval("(my-when true 1 2 3)"),
+            Ok(Expression::Number(3))
+        );
+        assert_eq!(env.parse_eval("(my-when false (nth))"), Ok(Expression::Nil));
+
+        // The expansion is evaluated in the caller's scope
+        assert_eq!(
+            env.parse_eval("(let [x 5] (my-when true (inc x)))"),
+            Ok(Expression::Number(6))
+        );
+        assert_eq!(
+            env.parse_eval("(defn f [n] (unless (> n 0) (f (dec n)) n))"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(env.parse_eval("(f 3)"), Ok(Expression::Number(3)));
+        assert_eq!(env.stack_height(), 1);
+    }
+
+    #[test]
+    fn macroexpand() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        env.parse_eval("(defmacro unless [c a b] `(if ~c ~b ~a))")
+            .unwrap();
+        env.parse_eval("(defmacro unless-not [c a b] `(unless (not ~c) ~a ~b))")
+            .unwrap();
+        assert_eq!(
+            env.parse_eval("(macroexpand-1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(macroexpand-1 '(unless x 1 2))"),
+            env.parse_eval("(quote (if x 2 1))")
+        );
+        assert_eq!(
+            env.parse_eval("(macroexpand-1 '(unless-not x 1 2))"),
+            env.parse_eval("(quote (unless (not x) 1 2))")
+        );
+        assert_eq!(
+            env.parse_eval("(macroexpand '(unless-not x 1 2))"),
+            env.parse_eval("(quote (if (not x) 2 1))")
+        );
+
+        // Forms which are not macro calls are returned unchanged
+        assert_eq!(
+            env.parse_eval("(macroexpand '(+ 1 2))"),
+            env.parse_eval("(quote (+ 1 2))")
+        );
+        assert_eq!(env.parse_eval("(macroexpand 5)"), Ok(Expression::Number(5)));
+    }
+
+    #[test]
+    fn gensym() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert!(env.parse_eval("(gensym)").unwrap().is_symbol());
+        assert_ne!(env.parse_eval("(gensym)"), env.parse_eval("(gensym)"));
+        assert_eq!(
+            env.parse_eval("(gensym 'tmp)"),
+            Ok(Expression::Symbol("tmp__4".to_string()))
+        );
+        assert_eq!(
+            env.parse_eval("(gensym \"x\")"),
+            Ok(Expression::Symbol("x__5".to_string()))
+        );
+        assert_eq!(
+            env.parse_eval("(gensym 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedSymbol)
+        );
+        assert_eq!(
+            env.parse_eval("(gensym 'a 'b)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+
+        // Generated symbols keep macro bindings from capturing the caller's names
+        env.parse_eval("(defmacro add-twice [x] (let [t (gensym)] `(let [~t ~x] (+ ~t ~t))))")
+            .unwrap();
+        assert_eq!(
+            env.parse_eval("(let [t 10] (add-twice (inc t)))"),
+            Ok(Expression::Number(22))
+        );
+    }
+
+    #[test]
+    fn op_let() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(let)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(let [a])").map_err(|e| e.kind()),
+            Err(ErrorKind::UnbalancedBindings)
+        );
+        assert_eq!(env.parse_eval("(let [myVar 2])"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(let [myVar 2] (+ myVar 1))"),
+            Ok(Expression::Number(3))
+        );
+        assert_eq!(
+            env.parse_eval("(let [myVar (inc 2)] (+ myVar 1))"),
+            Ok(Expression::Number(4))
+        );
+        assert_eq!(
+            env.parse_eval("(let [a 1 b 2] (+ a b))"),
+            Ok(Expression::Number(3))
+        );
+        assert_eq!(
+            env.parse_eval("(let [a 1 b 2] (+ a b) (inc a))"),
+            Ok(Expression::Number(2))
+        );
+    }
+
+    #[test]
+    fn op_loop() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(loop)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(loop [a])").map_err(|e| e.kind()),
+            Err(ErrorKind::UnbalancedBindings)
+        );
+        assert_eq!(
+            env.parse_eval("(loop [1 2])").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedSymbol)
+        );
+        assert_eq!(env.parse_eval("(loop [a 2])"), Ok(Expression::Nil));
+        assert_eq!(env.parse_eval("(loop [a 2] a)"), Ok(Expression::Number(2)));
+        assert_eq!(
+            env.parse_eval("(loop [i 1 acc 1] (if (> i 5) acc (recur (inc i) (* acc i))))"),
+            Ok(Expression::Number(120))
+        );
+
+        // Iterating does not grow the stack
+        assert_eq!(
+            env.parse_eval("(loop [i 0] (if (< i 100000) (recur (inc i)) i))"),
+            Ok(Expression::Number(100000))
+        );
+        assert_eq!(env.stack_height(), 1);
+
+        // Loops may be nested, and used from within functions
+        assert_eq!(
+            env.parse_eval(
+                "(defn sum-to [n] (loop [i 0 acc 0] (if (> i n) acc (recur (inc i) (+ acc i)))))"
+            ),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(
+            env.parse_eval(
+                "(loop [n 3 acc 0] (if (== n 0) acc (recur (dec n) (+ acc (sum-to n)))))"
+            ),
+            Ok(Expression::Number(10))
+        );
+
+        // The number of values must match the number of loop bindings
+        assert_eq!(
+            env.parse_eval("(loop [a 1 b 2] (if (== a 1) (recur 2) a))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(loop [a 1] (if (== a 1) (recur 2 3) a))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+    }
+
+    #[test]
+    fn recur() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(recur)").map_err(|e| e.kind()),
+            Err(ErrorKind::RecurOutsideTail)
+        );
+        assert_eq!(
+            env.parse_eval("(recur 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::RecurOutsideTail)
+        );
+        assert_eq!(
+            env.parse_eval("(loop [a 1] (+ 1 (recur 2)))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::RecurOutsideTail)
+        );
+        assert_eq!(
+            env.parse_eval("(loop [a 1] (do (recur 2) a))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::RecurOutsideTail)
+        );
+        assert_eq!(
+            env.parse_eval("(loop [a 1] (let [b (recur 2)] a))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::RecurOutsideTail)
+        );
+
+        // A function called from a loop body cannot continue that loop
+        assert_eq!(
+            env.parse_eval("(defn again [] (recur 2))"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(
+            env.parse_eval("(loop [a 1] (if (== a 1) (again) a))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::RecurOutsideTail)
+        );
+        assert_eq!(
+            env.parse_eval("(loop [a 1] (if (== a 1) ((fn [] (recur 2))) a))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::RecurOutsideTail)
+        );
+        assert_eq!(env.stack_height(), 1);
+    }
+
+    #[test]
+    fn quote() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(quote)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(quote x)"),
+            Ok(Expression::Symbol("x".to_string()))
+        );
+        assert_eq!(
+            env.parse_eval("(quote (+ 1 2))"),
+            Ok(Expression::List(vec![
+                Expression::Symbol("+".to_string()),
+                Expression::Number(1),
+                Expression::Number(2)
+            ]))
+        );
+        assert_eq!(
+            env.parse_eval("(do 'x)"),
+            Ok(Expression::Symbol("x".to_string()))
+        );
+        assert_eq!(
+            env.parse_eval("(do '[a b])"),
+            Ok(Expression::Vector(vec![
+                Expression::Symbol("a".to_string()),
+                Expression::Symbol("b".to_string())
+            ]))
+        );
+        assert_eq!(
+            env.parse_eval("(nth '(1 2 3) 1)"),
+            Ok(Expression::Number(2))
+        );
+    }
+
+    #[test]
+    fn quasiquote() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        env.parse_eval("(do (def b 2) (def c [3 4]))").unwrap();
+        assert_eq!(
+            env.parse_eval("(do `(a ~b))"),
+            env.parse_eval("(list 'a 2)")
+        );
+        assert_eq!(
+            env.parse_eval("(do `(a ~b ~@c 5))"),
+            env.parse_eval("(list 'a 2 3 4 5)")
+        );
+        assert_eq!(
+            env.parse_eval("(do `[a ~b ~@c])"),
+            env.parse_eval("(vector 'a 2 3 4)")
+        );
+        assert_eq!(
+            env.parse_eval("(do `(a (b ~(inc b)) [~@c]))"),
+            env.parse_eval("(list 'a (list 'b 3) [3 4])")
+        );
+        assert_eq!(env.parse_eval("(do `~b)"), Ok(Expression::Number(2)));
+        assert_eq!(
+            env.parse_eval("(do `~@c)").map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedSymbol)
+        );
+        assert_eq!(
+            env.parse_eval("(do `(~@b))").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+
+        // Maps and sets are quasiquoted too, and may be spliced into if sets
+        assert_eq!(
+            env.parse_eval("(do `{a ~b :c [~@c]})"),
+            env.parse_eval("(into {} [['a 2] [:c [3 4]]])")
+        );
+        assert_eq!(
+            env.parse_eval("(do `#{a ~b ~@c})"),
+            env.parse_eval("(set ['a 2 3 4])")
+        );
+        assert_eq!(
+            env.parse_eval("(do `(f {:k #{~b}}))"),
+            env.parse_eval("(list 'f {:k #{2}})")
+        );
+        assert_eq!(
+            env.parse_eval("(do `{:k ~@c})").map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedSymbol)
+        );
+
+        // The unquoted forms of a nested quasiquote are evaluated by the outer one
+        assert_eq!(
+            env.parse_eval("(do `(a `(b ~b)))"),
+            env.parse_eval("(list 'a (list 'quasiquote (list 'b 2)))")
+        );
+
+        // Quasiquoted code can be built up, then evaluated
+        assert_eq!(
+            env.parse_eval("(let [f (fn [x] `(+ ~x ~@c))] (f 1))"),
+            env.parse_eval("(list '+ 1 3 4)")
+        );
+    }
+
+    #[test]
+    fn list() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(list)"), Ok(Expression::List(vec![])));
+        assert_eq!(
+            env.parse_eval("(list 1 (inc 1))"),
+            Ok(Expression::List(vec![
+                Expression::Number(1),
+                Expression::Number(2)
+            ]))
+        );
+    }
+
+    #[test]
+    fn vec() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(vec)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(vec 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+        assert_eq!(env.parse_eval("(vec nil)"), Ok(Expression::Vector(vec![])));
+        assert_eq!(
+            env.parse_eval("(vec '(1 2))"),
+            env.parse_eval("(vector 1 2)")
+        );
+        assert_eq!(
+            env.parse_eval("(vec [1 2])"),
+            env.parse_eval("(vector 1 2)")
+        );
+    }
+
+    #[test]
+    fn concat() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(concat)"), Ok(Expression::List(vec![])));
+        assert_eq!(
+            env.parse_eval("(concat [1] 2)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+        assert_eq!(
+            env.parse_eval("(concat [1] nil '(2 3) [])"),
+            env.parse_eval("(list 1 2 3)")
+        );
+    }
+
+    #[test]
+    fn conj() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(conj [1] 2 3)"),
+            env.parse_eval("(vector 1 2 3)")
+        );
+        assert_eq!(
+            env.parse_eval("(conj '(1) 2 3)"),
+            env.parse_eval("(list 3 2 1)")
+        );
+        assert_eq!(env.parse_eval("(conj nil 1)"), env.parse_eval("(list 1)"));
+        assert_eq!(
+            env.parse_eval("(conj {:a 1} [:b 2])"),
+            env.parse_eval("(do {:a 1 :b 2})")
+        );
+        assert_eq!(env.parse_eval("(conj [1])"), env.parse_eval("(vector 1)"));
+        assert_eq!(
+            env.parse_eval("(conj {} 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+        assert_eq!(
+            env.parse_eval("(conj 1 2)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn op_if() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(if)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(if (< 10 11 12) true false)"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.parse_eval("(if (< 10 11 10) true false)"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(if (inc 10) true false)"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.parse_eval("(if true true false)"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.parse_eval("(if false true false)"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(if nil true false)"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(if (dec 1) true false)"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(env.parse_eval("(if false 1)"), Ok(Expression::Nil));
+        assert_eq!(env.parse_eval("(if 0 1)"), Ok(Expression::Number(1)));
+        assert_eq!(
+            env.parse_eval("(if true 1 2 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+    }
+
+    #[test]
+    fn op_when() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(when)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(env.parse_eval("(when true)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(when 0 (def w 1) (+ w 1))"),
+            Ok(Expression::Number(2))
+        );
+        assert_eq!(env.parse_eval("(when nil (missing))"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(when-not false 1 2)"),
+            Ok(Expression::Number(2))
+        );
+        assert_eq!(
+            env.parse_eval("(when-not 1 (missing))"),
+            Ok(Expression::Nil)
+        );
+    }
+
+    #[test]
+    fn op_cond() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(cond)"), Ok(Expression::Nil));
+        let grade = "(defn grade [n]
+                       (cond (>= n 90) :a
+                             (>= n 80) :b
+                             :else :c))";
+        env.parse_eval(grade).unwrap();
+        assert_eq!(
+            env.parse_eval("(grade 95)"),
+            Ok(Expression::Keyword("a".to_string()))
+        );
+        assert_eq!(
+            env.parse_eval("(grade 85)"),
+            Ok(Expression::Keyword("b".to_string()))
+        );
+        assert_eq!(
+            env.parse_eval("(grade 10)"),
+            Ok(Expression::Keyword("c".to_string()))
+        );
+        assert_eq!(env.parse_eval("(cond false 1 nil 2)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(cond true)").map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedArgs)
+        );
+    }
+
+    #[test]
+    fn op_case() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        let describe = "(defn describe [x]
+                          (case x
+                            1 \"one\"
+                            :two \"two\"
+                            (3 4) \"few\"
+                            [5] \"vec\"
+                            \"many\"))";
+        env.parse_eval(describe).unwrap();
+        let describe =
+            |env: &mut Environment, x: &str| env.parse_eval(&alloc::format!("(describe {})", x));
+        let string = |s: &str| Ok(Expression::String(s.to_string()));
+        assert_eq!(describe(&mut env, "1"), string("one"));
+        assert_eq!(describe(&mut env, ":two"), string("two"));
+        assert_eq!(describe(&mut env, "4"), string("few"));
+        assert_eq!(describe(&mut env, "[5]"), string("vec"));
+        assert_eq!(describe(&mut env, "9"), string("many"));
+        assert_eq!(
+            env.parse_eval("(case 'b a 1 b 2)"),
+            Ok(Expression::Number(2))
+        );
+        assert_eq!(
+            env.parse_eval("(case 3 1 2)").map_err(|e| e.kind()),
+            Err(ErrorKind::DataNotFound)
+        );
+        assert_eq!(
+            env.parse_eval("(case 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::DataNotFound)
+        );
+        assert_eq!(
+            env.parse_eval("(case 3 :default)"),
+            Ok(Expression::Keyword("default".to_string()))
+        );
+    }
+
+    #[test]
+    fn op_if_let() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(if-let [x (get {:a 1} :a)] (+ x 1) :none)"),
+            Ok(Expression::Number(2))
+        );
+        assert_eq!(
+            env.parse_eval("(if-let [x (get {:a 1} :b)] (+ x 1) :none)"),
+            Ok(Expression::Keyword("none".to_string()))
+        );
+        assert_eq!(env.parse_eval("(if-let [x false] x)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(if-let [[a b] [1 2]] (+ a b))"),
+            Ok(Expression::Number(3))
+        );
+        assert_eq!(
+            env.parse_eval("(when-let [{:keys [n]} {:n 2}] (def m n) (* m 3))"),
+            Ok(Expression::Number(6))
+        );
+        assert_eq!(
+            env.parse_eval("(when-let [x nil] (missing))"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(
+            env.parse_eval("(if-let [x 1 y 2] x)").map_err(|e| e.kind()),
+            Err(ErrorKind::UnbalancedBindings)
+        );
+        assert_eq!(
+            env.parse_eval("(if-let [x] x)").map_err(|e| e.kind()),
+            Err(ErrorKind::UnbalancedBindings)
+        );
+        assert_eq!(
+            env.parse_eval("(if-let x 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedVector)
+        );
+    }
+
+    #[test]
+    fn op_do() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(do)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(do true true false)"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(do (+ 1 3) (- 9 (+ 8 1)))"),
+            Ok(Expression::Number(0))
+        );
+
+        // Body forms are evaluated in order
+        assert_eq!(
+            env.parse_eval("(do (def order 1) (def order 2) order)"),
+            Ok(Expression::Number(2))
+        );
+    }
+
+    #[test]
+    fn op_while() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(while)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(env.parse_eval("(while false)"), Ok(Expression::Nil));
+        assert_eq!(env.parse_eval("(def myVar 8)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(do (while (> myVar 3) (def myVar (dec myVar))) myVar)"),
+            Ok(Expression::Number(3))
+        );
+    }
+
+    #[test]
+    fn doseq() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(doseq)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(doseq [a])").map_err(|e| e.kind()),
+            Err(ErrorKind::UnbalancedBindings)
+        );
+        assert_eq!(
+            env.parse_eval("(doseq [a [1 2] b [9]])")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::UnbalancedBindings)
+        );
+        assert_eq!(
+            env.parse_eval("(doseq [a [1] b [9 8]])")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::UnbalancedBindings)
+        );
+        assert_eq!(
+            env.parse_eval("(doseq [a 1])").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+
+        // Bindings are validated as for `let`, before anything is evaluated
+        assert_eq!(
+            env.parse_eval("(doseq [a [1] b])").map_err(|e| e.kind()),
+            Err(ErrorKind::UnbalancedBindings)
+        );
+        assert_eq!(
+            env.parse_eval("(doseq [1 [2] b 3])").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedSymbol)
+        );
+        assert_eq!(
+            env.parse_eval("(doseq [a [1 2] b [9 8]])"),
+            Ok(Expression::Nil)
+        );
+        // Define some vars
+        env.parse_eval("(do (def sum 0) (def prod 1))").unwrap();
+        assert_eq!(
+            env.parse_eval(
+                "(doseq [a [1 2] b [9 8]] (def sum (+ sum a b)) (def prod (* prod a b)))"
+            ),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(env.parse_eval("(+ sum 0)"), Ok(Expression::Number(20)));
+        assert_eq!(env.parse_eval("(+ prod 0)"), Ok(Expression::Number(144)));
+
+        // Collections are evaluated, and may be of any kind
+        env.parse_eval("(do (def xs [1 2 3]) (def seen []))")
+            .unwrap();
+        env.parse_eval("(doseq [x xs i (range 3)] (def seen (conj seen (* x i))))")
+            .unwrap();
+        assert_eq!(env.parse_eval("(do seen)"), env.parse_eval("(do [0 2 6])"));
+        assert_eq!(
+            env.parse_eval("(doseq [[k v] {:a 1} x nil] (def seen 0))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::UnbalancedBindings)
+        );
+        env.parse_eval("(doseq [[k v] {:a 1}] (def seen [k v]))")
+            .unwrap();
+        assert_eq!(env.parse_eval("(do seen)"), env.parse_eval("(do [:a 1])"));
+    }
+
+    #[test]
+    fn dotimes() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(dotimes)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(dotimes [n 5])").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(dotimes true (+ n 10))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::ImpossibleConversion)
+        );
+        // Define some vars
         env.parse_eval("(do (def sum 0))").unwrap();
         assert_eq!(
-            env.parse_eval("(dotimes [4 5] (+ sum n))"),
-            Err(Error::ExpectedSymbol)
+            env.parse_eval("(dotimes [4 5] (+ sum n))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedSymbol)
         );
         assert_eq!(
             env.parse_eval("(dotimes [n 5] (def sum (+ sum n)))"),
             Ok(Expression::Nil)
         );
         assert_eq!(env.parse_eval("(+ sum 0)"), Ok(Expression::Number(10)));
+
+        // The name to bind is not evaluated, but the count is
+        env.parse_eval("(do (def sum 0) (def times 2))").unwrap();
+        assert_eq!(
+            env.parse_eval("(dotimes [i (+ times 1)] (def sum (+ sum i)))"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(env.parse_eval("(+ sum 0)"), Ok(Expression::Number(3)));
     }
 
     #[test]
@@ -460,15 +1766,42 @@ mod tests {
             Expression::Number(3)
         ]
         .into_iter()));
+
+        // Vector literals evaluate their items in order, like `vector`, unless
+        // quoted
+        env.parse_eval("(def x 1)").unwrap();
+        assert_eq!(
+            env.parse_eval("(do [x (inc x) (+ x 2)])"),
+            env.parse_eval("(vector 1 2 3)")
+        );
+        assert_eq!(
+            env.parse_eval("(quote [x (inc x)])"),
+            Ok(Expression::Vector(vec![
+                Expression::Symbol("x".into()),
+                Expression::List(vec![
+                    Expression::Symbol("inc".into()),
+                    Expression::Symbol("x".into())
+                ])
+            ]))
+        );
     }
 
     #[test]
     fn nth() {
         let mut env = Environment::new();
         env.load_default_builtins().unwrap();
-        assert_eq!(env.parse_eval("(nth)"), Err(Error::TooFewArgs));
-        assert_eq!(env.parse_eval("(nth [1 2 3])"), Err(Error::TooFewArgs));
-        assert_eq!(env.parse_eval("(nth 3)"), Err(Error::ExpectedVector));
+        assert_eq!(
+            env.parse_eval("(nth)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(nth [1 2 3])").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(nth 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedVector)
+        );
         assert_eq!(env.parse_eval("(nth [1 2 3] 0)"), Ok(Expression::Number(1)));
         assert_eq!(env.parse_eval("(nth [1 2 3] 1)"), Ok(Expression::Number(2)));
         assert_eq!(env.parse_eval("(nth [1 2 3] 2)"), Ok(Expression::Number(3)));
@@ -478,8 +1811,25 @@ mod tests {
             Ok(Expression::Number(9))
         );
         assert_eq!(
-            env.parse_eval("(nth [1 2 3] 4 9 8)"),
-            Err(Error::TooManyArgs)
+            env.parse_eval("(nth [1 2 3] 4 9 8)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+
+        // The collection and index are evaluated, and the default only when it
+        // is needed
+        env.parse_eval("(def v [1 2 3])").unwrap();
+        assert_eq!(env.parse_eval("(nth v (inc 0))"), Ok(Expression::Number(2)));
+        assert_eq!(
+            env.parse_eval("(nth (vector 4 5) 0)"),
+            Ok(Expression::Number(4))
+        );
+        assert_eq!(
+            env.parse_eval("(nth v 0 (missing))"),
+            Ok(Expression::Number(1))
+        );
+        assert_eq!(
+            env.parse_eval("(nth v 5 (inc 9))"),
+            Ok(Expression::Number(10))
         );
     }
 
@@ -487,22 +1837,53 @@ mod tests {
     fn peek() {
         let mut env = Environment::new();
         env.load_default_builtins().unwrap();
-        assert_eq!(env.parse_eval("(peek)"), Err(Error::TooFewArgs));
-        assert_eq!(env.parse_eval("(peek [1 2 3] 3)"), Err(Error::TooManyArgs));
-        assert_eq!(env.parse_eval("(peek 3)"), Err(Error::ExpectedVector));
+        assert_eq!(
+            env.parse_eval("(peek)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(peek [1 2 3] 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(peek 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedVector)
+        );
         assert_eq!(env.parse_eval("(peek [1 2 3])"), Ok(Expression::Number(3)));
+
+        // The argument is evaluated
+        env.parse_eval("(def v [1 2])").unwrap();
+        assert_eq!(env.parse_eval("(peek v)"), Ok(Expression::Number(2)));
+        assert_eq!(env.parse_eval("(peek (pop v))"), Ok(Expression::Number(1)));
     }
 
     #[test]
     fn pop() {
         let mut env = Environment::new();
         env.load_default_builtins().unwrap();
-        assert_eq!(env.parse_eval("(pop)"), Err(Error::TooFewArgs));
-        assert_eq!(env.parse_eval("(pop [1 2 3] 3)"), Err(Error::TooManyArgs));
-        assert_eq!(env.parse_eval("(pop 3)"), Err(Error::ExpectedVector));
+        assert_eq!(
+            env.parse_eval("(pop)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(pop [1 2 3] 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(pop 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedVector)
+        );
         assert_eq!(
             env.parse_eval("(pop [1 2 3])"),
             env.parse_eval("(vector 1 2)")
         );
+
+        // The argument is evaluated
+        env.parse_eval("(def v [1 2])").unwrap();
+        assert_eq!(env.parse_eval("(pop v)"), env.parse_eval("(vector 1)"));
+        assert_eq!(
+            env.parse_eval("(pop (vector 1 2 3))"),
+            env.parse_eval("(vector 1 2)")
+        );
     }
 }
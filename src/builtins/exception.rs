@@ -0,0 +1,416 @@
+extern crate alloc;
+
+use crate::expression::FnBody;
+use crate::Environment;
+use crate::Expression;
+use crate::{make_builtin__a_and_b, make_builtin__args, make_builtin__x};
+use crate::{Error, ErrorKind};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+// Make the value produced by `ex-info`: a map holding a message and a map of
+// data.
+fn ex_info(message: String, data: Expression) -> Expression {
+    Expression::Map(BTreeMap::from([
+        (Expression::keyword("message"), Expression::String(message)),
+        (Expression::keyword("data"), data),
+    ]))
+}
+
+// The value bound by a `catch` clause. A thrown value is bound as-is. Any other
+// error is bound as if made by `ex-info`, with its kind and context as data.
+// The message includes any detail the error carries, as when it is displayed.
+fn error_value(err: Error) -> Expression {
+    let kind = err.kind();
+    let context = err.context.map(|context| *context).unwrap_or_default();
+    if let Some(thrown) = context.thrown {
+        return thrown;
+    }
+    let mut message = format!("{}", kind);
+    if let Some(detail) = &context.message {
+        message.push(' ');
+        message.push_str(detail);
+    }
+    let mut data = BTreeMap::from([(
+        Expression::keyword("kind"),
+        Expression::keyword(kind.name()),
+    )]);
+    if let Some((expected, actual)) = context.arity {
+        message.push_str(&format!(" Expected {}, got {}.", expected, actual));
+        data.insert(
+            Expression::keyword("args"),
+            Expression::Number(actual as i64),
+        );
+        data.insert(
+            Expression::keyword("min-args"),
+            Expression::Number(expected.min as i64),
+        );
+        if let Some(max) = expected.max {
+            data.insert(
+                Expression::keyword("max-args"),
+                Expression::Number(max as i64),
+            );
+        }
+    }
+    if let Some(symbol) = context.symbol {
+        data.insert(Expression::keyword("symbol"), Expression::Symbol(symbol));
+    }
+    if let Some(function) = context.function {
+        data.insert(
+            Expression::keyword("function"),
+            Expression::String(function),
+        );
+    }
+    if let Some(value_type) = context.value_type {
+        data.insert(
+            Expression::keyword("type"),
+            Expression::String(value_type.to_string()),
+        );
+    }
+    ex_info(message, Expression::Map(data))
+}
+
+// A `catch` clause of a `try` form: `(catch e body...)` catches every error,
+// while `(catch :kind e body...)` only catches errors of the named kind, which
+// must be the name of an `ErrorKind`, such as `:math-error` or `:thrown`.
+struct Catch {
+    kind: Option<ErrorKind>,
+    name: String,
+    body: Vec<Expression>,
+}
+
+impl Catch {
+    fn matches(&self, err: &Error) -> bool {
+        match self.kind {
+            Some(kind) => kind == err.kind(),
+            None => true,
+        }
+    }
+}
+
+// If `form` is a list starting with the given symbol, return the rest of the
+// list.
+fn clause(form: &Expression, name: &str) -> Option<Vec<Expression>> {
+    match form.clone().unspanned() {
+        Expression::List(mut items) if items.first() == Some(&Expression::Symbol(name.into())) => {
+            items.remove(0);
+            Some(items)
+        }
+        _ => None,
+    }
+}
+
+fn parse_catch(mut items: Vec<Expression>) -> Result<Catch, Error> {
+    let kind = match items.first() {
+        Some(Expression::Keyword(name)) => {
+            let kind = ErrorKind::from_name(name).ok_or_else(|| {
+                Error::new(ErrorKind::UnexpectedSymbol)
+                    .with_function("catch")
+                    .with_message(&format!("`:{}` is not a kind of error.", name))
+            })?;
+            items.remove(0);
+            Some(kind)
+        }
+        _ => None,
+    };
+    if items.is_empty() {
+        return Err(Error::new(ErrorKind::TooFewArgs).with_function("catch"));
+    }
+    match items.remove(0) {
+        Expression::Symbol(name) => Ok(Catch {
+            kind,
+            name,
+            body: items,
+        }),
+        _ => Err(Error::new(ErrorKind::ExpectedSymbol).with_function("catch")),
+    }
+}
+
+make_builtin__x!(THROW, |env: &mut Environment, x: Expression| {
+    Err(Error::thrown(env.eval(x)?))
+});
+
+make_builtin__a_and_b!(EX_INFO, |env: &mut Environment,
+                                 a: Expression,
+                                 b: Expression| {
+    let message = match env.eval(a)? {
+        Expression::String(s) => s,
+        expr => {
+            return Err(Error::new(ErrorKind::TypeMismatch)
+                .with_message("Expected a string.")
+                .with_value(&expr))
+        }
+    };
+    match env.eval(b)? {
+        data @ (Expression::Map(_) | Expression::Nil) => Ok(ex_info(message, data)),
+        expr => Err(Error::new(ErrorKind::TypeMismatch)
+            .with_message("Expected a map.")
+            .with_value(&expr)),
+    }
+});
+
+make_builtin__x!(EX_MESSAGE, |env: &mut Environment, x: Expression| {
+    match env.eval(x)? {
+        Expression::Map(m) => Ok(m
+            .get(&Expression::keyword("message"))
+            .cloned()
+            .unwrap_or(Expression::Nil)),
+        _ => Ok(Expression::Nil),
+    }
+});
+
+make_builtin__x!(EX_DATA, |env: &mut Environment, x: Expression| {
+    match env.eval(x)? {
+        Expression::Map(m) => Ok(m
+            .get(&Expression::keyword("data"))
+            .cloned()
+            .unwrap_or(Expression::Nil)),
+        _ => Ok(Expression::Nil),
+    }
+});
+
+// `(try body... (catch e handler...) (finally cleanup...))` evaluates the body,
+// and if it fails, evaluates the first `catch` clause which matches the error,
+// with the error bound to the clause's name. The `finally` forms are then
+// evaluated for their side effects, whether or not the body or handler failed.
+// Any frames pushed by the failed forms have already been popped by the time
+// the handler runs.
+make_builtin__args!(
+    TRY,
+    |_env: &mut Environment| { Ok(Expression::Nil) },
+    |env: &mut Environment, args: Vec<_>| {
+        let mut body = Vec::new();
+        let mut catches = Vec::new();
+        let mut finally = None;
+        for form in args {
+            if finally.is_some() {
+                return Err(Error::new(ErrorKind::UnexpectedArgs)
+                    .with_function("try")
+                    .with_message("A finally clause must come last."));
+            } else if let Some(items) = clause(&form, "catch") {
+                catches.push(parse_catch(items)?);
+            } else if let Some(items) = clause(&form, "finally") {
+                finally = Some(items);
+            } else if catches.is_empty() {
+                body.push(form);
+            } else {
+                return Err(Error::new(ErrorKind::UnexpectedArgs)
+                    .with_function("try")
+                    .with_message("The body must come before any catch clauses."));
+            }
+        }
+
+        let res = match env.eval_program(body) {
+            Err(err) => match catches.into_iter().find(|catch| catch.matches(&err)) {
+                Some(catch) => {
+                    env.push_stack(&catch.name, error_value(err))?;
+                    env.eval_program(catch.body)
+                }
+                None => Err(err),
+            },
+            res => res,
+        };
+        if let Some(finally) = finally {
+            env.eval_program(finally)?;
+        }
+        res
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::Environment;
+    use crate::ErrorKind;
+    use crate::Expression;
+    use alloc::string::ToString;
+
+    fn string(s: &str) -> Expression {
+        Expression::String(s.to_string())
+    }
+
+    #[test]
+    fn throw() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        let err = env.parse_eval("(throw :oops)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Thrown);
+        assert_eq!(
+            err.context.unwrap().thrown,
+            Some(Expression::Keyword("oops".to_string()))
+        );
+        assert_eq!(
+            alloc::format!("{}", env.parse_eval("(throw [1 2])").unwrap_err()),
+            "An exception was thrown. Value: [ 1 2 ] (at line 1, column 1)"
+        );
+    }
+
+    #[test]
+    fn ex_info() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(ex-message (ex-info \"bad\" {:code 1}))"),
+            Ok(string("bad"))
+        );
+        assert_eq!(
+            env.parse_eval("(ex-data (ex-info \"bad\" {:code 1}))"),
+            env.parse_eval("(do {:code 1})")
+        );
+        assert_eq!(env.parse_eval("(ex-data 5)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(ex-info :bad {})").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+        assert_eq!(
+            env.parse_eval("(ex-info \"bad\" 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn try_catch() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(try)"), Ok(Expression::Nil));
+        assert_eq!(env.parse_eval("(try 1 2)"), Ok(Expression::Number(2)));
+        assert_eq!(
+            env.parse_eval("(try (throw 1) (catch e (+ e 1)))"),
+            Ok(Expression::Number(2))
+        );
+        assert_eq!(
+            env.parse_eval(
+                "(try (throw (ex-info \"bad\" {:code 7}))
+                      (catch e (:code (ex-data e))))"
+            ),
+            Ok(Expression::Number(7))
+        );
+
+        // Internal errors are caught by kind
+        assert_eq!(
+            env.parse_eval(
+                "(try (/ 1 0)
+                      (catch :data-not-found e :missing)
+                      (catch :math-error e (:kind (ex-data e))))"
+            ),
+            Ok(Expression::Keyword("math-error".to_string()))
+        );
+        assert_eq!(
+            env.parse_eval("(try (missing-var) (catch e (ex-message e)))"),
+            Ok(string("Expected a function."))
+        );
+        assert_eq!(
+            env.parse_eval("(try undefined (catch e (:symbol (ex-data e))))"),
+            Ok(Expression::Symbol("undefined".to_string()))
+        );
+        assert_eq!(
+            env.parse_eval("(try (/ 1 0) (catch :thrown e 0))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::MathError)
+        );
+        assert_eq!(
+            env.parse_eval("(try (throw 1) (catch e (throw (+ e 1))))")
+                .unwrap_err()
+                .context
+                .unwrap()
+                .thrown,
+            Some(Expression::Number(2))
+        );
+
+        assert_eq!(
+            env.parse_eval("(try (catch e 1) 2)").map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(try 1 (finally 2) (catch e 3))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(try 1 (catch 2 3))").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedSymbol)
+        );
+
+        // Only the names of kinds of error may be caught
+        assert_eq!(
+            env.parse_eval("(try (throw 1) (catch :tyop-mismatch e 2))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedSymbol)
+        );
+        assert_eq!(
+            env.parse_eval("(try (throw (ex-info \"bad\" {:kind :oops})) (catch :oops e 2))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedSymbol)
+        );
+
+        // The detail an error carries is kept
+        assert_eq!(
+            env.parse_eval("(try (subs 1 2) (catch e (ex-message e)))"),
+            Ok(string("Mismatched types. Expected a string."))
+        );
+        assert_eq!(
+            env.parse_eval("(try ((fn [a b] a) 1) (catch e [(ex-message e) (ex-data e)]))"),
+            env.parse_eval(
+                "(do [\"Not enough args were supplied. Expected 2 args, got 1.\"
+                      {:kind :too-few-args :args 1 :min-args 2 :max-args 2}])"
+            )
+        );
+    }
+
+    #[test]
+    fn finally() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        env.parse_eval("(def log [])").unwrap();
+        assert_eq!(
+            env.parse_eval("(try 1 (finally (def log (conj log :a))))"),
+            Ok(Expression::Number(1))
+        );
+        assert_eq!(
+            env.parse_eval("(try (throw 1) (catch e e) (finally (def log (conj log :b))))"),
+            Ok(Expression::Number(1))
+        );
+        assert_eq!(
+            env.parse_eval("(try (/ 1 0) (finally (def log (conj log :c))))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::MathError)
+        );
+        assert_eq!(
+            env.parse_eval("(do log)"),
+            env.parse_eval("(do [:a :b :c])")
+        );
+
+        // Errors in a finally clause replace the result
+        assert_eq!(
+            env.parse_eval("(try 1 (finally (throw 2)))")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::Thrown)
+        );
+    }
+
+    #[test]
+    fn unwinding() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        env.parse_eval("(defn fail [x] (let [y 1] (+ x y :k)))")
+            .unwrap();
+        let height = env.stack_height();
+        assert_eq!(
+            env.parse_eval(
+                "(let [a 1]
+                   (try (let [b 2] (fail b))
+                        (catch :impossible-conversion e (+ a 1))))"
+            ),
+            Ok(Expression::Number(2))
+        );
+        assert_eq!(env.stack_height(), height);
+
+        // Bindings made by the failed body are no longer visible to the handler
+        assert_eq!(
+            env.parse_eval("(let [b 1] (try (let [b 2] (throw b)) (catch e b)))"),
+            Ok(Expression::Number(1))
+        );
+        assert_eq!(env.stack_height(), height);
+    }
+}
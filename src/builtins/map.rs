@@ -0,0 +1,585 @@
+extern crate alloc;
+
+use crate::expression::FnBody;
+use crate::Environment;
+use crate::Expression;
+use crate::{
+    make_builtin__a_and_b, make_builtin__a_b_and_c, make_builtin__args, make_builtin__x,
+    make_builtin__x_and_ys,
+};
+use crate::{Error, ErrorKind};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+// Evaluate an expression, which must produce a map. `nil` is treated as an
+// empty map.
+fn eval_map(
+    env: &mut Environment,
+    expr: Expression,
+) -> Result<BTreeMap<Expression, Expression>, Error> {
+    match env.eval(expr)? {
+        Expression::Map(m) => Ok(m),
+        Expression::Nil => Ok(BTreeMap::new()),
+        expr => Err(Error::new(ErrorKind::TypeMismatch)
+            .with_message("Expected a map.")
+            .with_value(&expr)),
+    }
+}
+
+// Evaluate an expression, which must produce a path of keys, as used by
+// `get-in`, `assoc-in` and `update-in`.
+fn eval_path(env: &mut Environment, expr: Expression) -> Result<Vec<Expression>, Error> {
+    env.eval(expr)?
+        .try_into()
+        .or(Err(Error::new(ErrorKind::TypeMismatch)))
+}
+
+// Pop the rest args of a builtin, which are absent if none were given.
+fn pop_rest(env: &mut Environment, name: &str) -> Result<Vec<Expression>, Error> {
+    match env.pop_stack_if_named(name) {
+        Ok(args) => args.try_into(),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+// Look up a key in a map, or an index in a vector. Looking anything up in `nil`
+// finds nothing.
+fn lookup(coll: &Expression, key: &Expression) -> Result<Option<Expression>, Error> {
+    match (coll, key) {
+        (Expression::Map(m), _) => Ok(m.get(key).cloned()),
+        (Expression::Vector(v), Expression::Number(idx)) => Ok(usize::try_from(*idx)
+            .ok()
+            .and_then(|idx| v.get(idx))
+            .cloned()),
+        (Expression::Vector(_), _) | (Expression::Nil, _) => Ok(None),
+        _ => Err(Error::new(ErrorKind::TypeMismatch).with_value(coll)),
+    }
+}
+
+// Look up a path of keys in nested collections.
+fn lookup_in(coll: &Expression, path: &[Expression]) -> Result<Option<Expression>, Error> {
+    path.iter()
+        .try_fold(Some(coll.clone()), |coll, key| match coll {
+            Some(coll) => lookup(&coll, key),
+            None => Ok(None),
+        })
+}
+
+// Associate a key with a value in a map, or an index with a value in a vector.
+// A vector may be extended by associating the index one past its end.
+// Associating into `nil` produces a new map.
+fn associate(coll: Expression, key: Expression, val: Expression) -> Result<Expression, Error> {
+    match (coll, key) {
+        (Expression::Map(mut m), key) => {
+            m.insert(key, val);
+            Ok(Expression::Map(m))
+        }
+        (Expression::Nil, key) => Ok(Expression::Map(BTreeMap::from([(key, val)]))),
+        (Expression::Vector(mut v), Expression::Number(idx)) => {
+            match usize::try_from(idx) {
+                Ok(idx) if idx < v.len() => v[idx] = val,
+                Ok(idx) if idx == v.len() => v.push(val),
+                _ => return Err(Error::new(ErrorKind::OutOfRange)),
+            }
+            Ok(Expression::Vector(v))
+        }
+        _ => Err(Error::new(ErrorKind::TypeMismatch)),
+    }
+}
+
+// Associate a value at a path of keys in nested collections, creating maps for
+// any keys which are missing.
+fn associate_in(
+    coll: Expression,
+    path: &[Expression],
+    val: Expression,
+) -> Result<Expression, Error> {
+    match path.split_first() {
+        Some((key, [])) => associate(coll, key.clone(), val),
+        Some((key, rest)) => {
+            let inner = lookup(&coll, key)?.unwrap_or(Expression::Nil);
+            let inner = associate_in(inner, rest, val)?;
+            associate(coll, key.clone(), inner)
+        }
+        None => Err(Error::new(ErrorKind::Empty)),
+    }
+}
+
+// `(get coll key)` or `(get coll key default)`. The default is only evaluated
+// if the key is not found.
+make_builtin__x_and_ys!(
+    GET,
+    |_env: &mut Environment, _x: Expression| { Err(Error::new(ErrorKind::TooFewArgs)) },
+    |env: &mut Environment, x: Expression, mut ys: Vec<_>| {
+        let coll = env.eval(x)?;
+        let dnf = match ys.len() {
+            1 => Expression::Nil,
+            2 => ys.remove(1),
+            _ => return Err(Error::new(ErrorKind::TooManyArgs)),
+        };
+        let key = env.eval(ys.remove(0))?;
+        match lookup(&coll, &key)? {
+            Some(val) => Ok(val),
+            None => env.eval(dnf),
+        }
+    }
+);
+
+// `(get-in coll path)` or `(get-in coll path default)`.
+make_builtin__x_and_ys!(
+    GET_IN,
+    |_env: &mut Environment, _x: Expression| { Err(Error::new(ErrorKind::TooFewArgs)) },
+    |env: &mut Environment, x: Expression, mut ys: Vec<_>| {
+        let coll = env.eval(x)?;
+        let dnf = match ys.len() {
+            1 => Expression::Nil,
+            2 => ys.remove(1),
+            _ => return Err(Error::new(ErrorKind::TooManyArgs)),
+        };
+        let path = eval_path(env, ys.remove(0))?;
+        match lookup_in(&coll, &path)? {
+            Some(val) => Ok(val),
+            None => env.eval(dnf),
+        }
+    }
+);
+
+make_builtin__x_and_ys!(
+    ASSOC,
+    |_env: &mut Environment, _x: Expression| { Err(Error::new(ErrorKind::TooFewArgs)) },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| {
+        if !ys.len().is_multiple_of(2) {
+            return Err(Error::new(ErrorKind::UnbalancedEntries));
+        }
+        let mut coll = env.eval(x)?;
+        let mut ys = ys.into_iter();
+        while let (Some(k), Some(v)) = (ys.next(), ys.next()) {
+            let k = env.eval(k)?;
+            coll = associate(coll, k, env.eval(v)?)?;
+        }
+        Ok(coll)
+    }
+);
+
+make_builtin__a_b_and_c!(
+    ASSOC_IN,
+    |env: &mut Environment, a: Expression, b: Expression, c: Expression| {
+        let coll = env.eval(a)?;
+        let path = eval_path(env, b)?;
+        associate_in(coll, &path, env.eval(c)?)
+    }
+);
+
+make_builtin__x_and_ys!(
+    DISSOC,
+    |env: &mut Environment, x: Expression| {
+        match env.eval(x)? {
+            expr @ (Expression::Map(_) | Expression::Nil) => Ok(expr),
+            _ => Err(Error::new(ErrorKind::TypeMismatch)),
+        }
+    },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| {
+        let mut m = match env.eval(x)? {
+            Expression::Map(m) => m,
+            Expression::Nil => return Ok(Expression::Nil),
+            _ => return Err(Error::new(ErrorKind::TypeMismatch)),
+        };
+        for y in ys {
+            m.remove(&env.eval(y)?);
+        }
+        Ok(Expression::Map(m))
+    }
+);
+
+// `(update m k f & args)` replaces the value of `k` with `(f value & args)`.
+pub const UPDATE: (&str, FnBody) = (
+    "m k f & args",
+    FnBody(|env: &mut Environment| {
+        let m = env.pop_stack_if_named("m")?;
+        let k = env.pop_stack_if_named("k")?;
+        let f = env.pop_stack_if_named("f")?;
+        let args = pop_rest(env, "args")?;
+
+        let coll = env.eval(m)?;
+        let key = env.eval(k)?;
+        let func = env.eval(f)?;
+        let mut args: Vec<_> = args.into_iter().map(|e| env.eval(e)).try_collect()?;
+        args.insert(0, lookup(&coll, &key)?.unwrap_or(Expression::Nil));
+        let val = env.apply(func, args)?;
+        associate(coll, key, val)
+    }),
+);
+
+// `(update-in m path f & args)` is like `update`, for a value nested at a path
+// of keys.
+pub const UPDATE_IN: (&str, FnBody) = (
+    "m path f & args",
+    FnBody(|env: &mut Environment| {
+        let m = env.pop_stack_if_named("m")?;
+        let path = env.pop_stack_if_named("path")?;
+        let f = env.pop_stack_if_named("f")?;
+        let args = pop_rest(env, "args")?;
+
+        let coll = env.eval(m)?;
+        let path = eval_path(env, path)?;
+        let func = env.eval(f)?;
+        let mut args: Vec<_> = args.into_iter().map(|e| env.eval(e)).try_collect()?;
+        args.insert(0, lookup_in(&coll, &path)?.unwrap_or(Expression::Nil));
+        let val = env.apply(func, args)?;
+        associate_in(coll, &path, val)
+    }),
+);
+
+make_builtin__x!(KEYS, |env: &mut Environment, x: Expression| {
+    Ok(Expression::Vector(eval_map(env, x)?.into_keys().collect()))
+});
+
+make_builtin__x!(VALS, |env: &mut Environment, x: Expression| {
+    Ok(Expression::Vector(
+        eval_map(env, x)?.into_values().collect(),
+    ))
+});
+
+// Maps contain their keys, vectors contain their indices, and sets contain
+// their items.
+make_builtin__a_and_b!(CONTAINS, |env: &mut Environment,
+                                  a: Expression,
+                                  b: Expression| {
+    let coll = env.eval(a)?;
+    let key = env.eval(b)?;
+    match coll {
+        Expression::Set(s) => Ok(Expression::Bool(s.contains(&key))),
+        Expression::Map(_) | Expression::Vector(_) | Expression::Nil => {
+            Ok(Expression::Bool(lookup(&coll, &key)?.is_some()))
+        }
+        _ => Err(Error::new(ErrorKind::TypeMismatch)),
+    }
+});
+
+// Merge maps from left to right, so that later values replace earlier ones.
+// Any `nil` args are skipped, and merging only `nil` produces `nil`.
+make_builtin__args!(
+    MERGE,
+    |_env: &mut Environment| { Ok(Expression::Nil) },
+    |env: &mut Environment, args: Vec<_>| {
+        let mut merged: Option<BTreeMap<_, _>> = None;
+        for arg in args {
+            match env.eval(arg)? {
+                Expression::Map(m) => merged.get_or_insert_with(BTreeMap::new).extend(m),
+                Expression::Nil => {}
+                _ => return Err(Error::new(ErrorKind::TypeMismatch)),
+            }
+        }
+        Ok(merged.map_or(Expression::Nil, Expression::Map))
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::Environment;
+    use crate::Expression;
+    use crate::{Error, ErrorKind};
+    use alloc::format;
+    use alloc::string::ToString;
+
+    fn parse(env: &mut Environment, s: &str) -> Result<Expression, Error> {
+        env.parse_eval(&format!("(quote {})", s))
+    }
+
+    #[test]
+    fn map_literal() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(let [a 1] {(quote x) a (quote y) (+ a 1)})"),
+            parse(&mut env, "{x 1 y 2}")
+        );
+        assert_eq!(
+            env.parse_eval("(== {1 2} {1 2})"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.parse_eval("(== {1 2} {1 3})"),
+            Ok(Expression::Bool(false))
+        );
+
+        // Keys must be distinct once evaluated, not only as written
+        let err = env.parse_eval("(let [a 1] {a :x 1 :y})").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DuplicateKey);
+        assert!(format!("{}", err).contains("The key 1 appears more than once"));
+    }
+
+    #[test]
+    fn keywords() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(do :status)"),
+            Ok(Expression::Keyword("status".to_string()))
+        );
+        env.parse_eval("(def person {:name \"Ada\" :age 36})")
+            .unwrap();
+        assert_eq!(
+            env.parse_eval("(:name person)"),
+            Ok(Expression::String("Ada".to_string()))
+        );
+        assert_eq!(env.parse_eval("(:email person)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(:email person \"none\")"),
+            Ok(Expression::String("none".to_string()))
+        );
+        assert_eq!(env.parse_eval("(:name nil)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(update person :age inc)"),
+            env.parse_eval("(do {:name \"Ada\" :age 37})")
+        );
+        assert_eq!(
+            env.parse_eval("(get-in {:a {:b 1}} [:a :b])"),
+            Ok(Expression::Number(1))
+        );
+        assert_eq!(
+            env.parse_eval("(:name)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(:name {} 1 2)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+
+        // Maps look up their arg when called
+        assert_eq!(env.parse_eval("(person :age)"), Ok(Expression::Number(36)));
+        assert_eq!(
+            env.parse_eval("({:a 1} (first [:a]))"),
+            Ok(Expression::Number(1))
+        );
+        assert_eq!(env.parse_eval("({:a 1} :b)"), Ok(Expression::Nil));
+        assert_eq!(env.parse_eval("({:a 1} :b 2)"), Ok(Expression::Number(2)));
+        assert_eq!(
+            env.parse_eval("(map {1 :one 2 :two} [2 1])"),
+            env.parse_eval("(list :two :one)")
+        );
+        assert_eq!(
+            env.parse_eval("({:a 1})").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("({:a 1} :a 2 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+    }
+
+    #[test]
+    fn get() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(get {1 2} 1)"), Ok(Expression::Number(2)));
+        assert_eq!(env.parse_eval("(get {1 2} 3)"), Ok(Expression::Nil));
+        assert_eq!(env.parse_eval("(get {1 2} 3 4)"), Ok(Expression::Number(4)));
+        assert_eq!(env.parse_eval("(get {1 nil} 1 4)"), Ok(Expression::Nil));
+        assert_eq!(env.parse_eval("(get [5 6] 1)"), Ok(Expression::Number(6)));
+        assert_eq!(env.parse_eval("(get [5 6] 2)"), Ok(Expression::Nil));
+        assert_eq!(env.parse_eval("(get nil 1)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(get 1 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+        assert_eq!(
+            env.parse_eval("(get {})").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(get {} 1 2 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+    }
+
+    #[test]
+    fn get_in() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(get-in {1 {2 [3 4]}} [1 2 1])"),
+            Ok(Expression::Number(4))
+        );
+        assert_eq!(
+            env.parse_eval("(get-in {1 {2 3}} [1 5])"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(
+            env.parse_eval("(get-in {1 {2 3}} [4 5] 6)"),
+            Ok(Expression::Number(6))
+        );
+        assert_eq!(
+            env.parse_eval("(get-in {1 {2 3}} [])"),
+            parse(&mut env, "{1 {2 3}}")
+        );
+        assert_eq!(
+            env.parse_eval("(get-in {} 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn assoc() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(assoc {1 2} 3 4 1 5)"),
+            parse(&mut env, "{1 5 3 4}")
+        );
+        assert_eq!(env.parse_eval("(assoc nil 1 2)"), parse(&mut env, "{1 2}"));
+        assert_eq!(
+            env.parse_eval("(assoc [1 2] 0 3 2 4)"),
+            parse(&mut env, "[3 2 4]")
+        );
+        assert_eq!(
+            env.parse_eval("(assoc [1 2] 3 4)").map_err(|e| e.kind()),
+            Err(ErrorKind::OutOfRange)
+        );
+        assert_eq!(
+            env.parse_eval("(assoc {} 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::UnbalancedEntries)
+        );
+        assert_eq!(
+            env.parse_eval("(assoc {})").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(assoc 1 2 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn assoc_in() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(assoc-in {1 {2 3}} [1 2] 4)"),
+            parse(&mut env, "{1 {2 4}}")
+        );
+        assert_eq!(
+            env.parse_eval("(assoc-in {} [1 2 3] 4)"),
+            parse(&mut env, "{1 {2 {3 4}}}")
+        );
+        assert_eq!(
+            env.parse_eval("(assoc-in {1 [2 3]} [1 0] 4)"),
+            parse(&mut env, "{1 [4 3]}")
+        );
+        assert_eq!(
+            env.parse_eval("(assoc-in {} [] 4)").map_err(|e| e.kind()),
+            Err(ErrorKind::Empty)
+        );
+    }
+
+    #[test]
+    fn dissoc() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(dissoc {1 2 3 4 5 6} 1 5 7)"),
+            parse(&mut env, "{3 4}")
+        );
+        assert_eq!(env.parse_eval("(dissoc {1 2})"), parse(&mut env, "{1 2}"));
+        assert_eq!(env.parse_eval("(dissoc nil 1)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(dissoc [1] 0)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn update() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(update {1 2} 1 inc)"),
+            parse(&mut env, "{1 3}")
+        );
+        assert_eq!(
+            env.parse_eval("(update {1 2} 1 + 10 20)"),
+            parse(&mut env, "{1 32}")
+        );
+        assert_eq!(
+            env.parse_eval("(update {} 1 (fn [x] (if x x (quote none))))"),
+            parse(&mut env, "{1 none}")
+        );
+        assert_eq!(
+            env.parse_eval("(update {1 (quote (a b))} 1 count)"),
+            parse(&mut env, "{1 2}")
+        );
+        assert_eq!(
+            env.parse_eval("(update {} 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+    }
+
+    #[test]
+    fn update_in() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(update-in {1 {2 3}} [1 2] * 2)"),
+            parse(&mut env, "{1 {2 6}}")
+        );
+        assert_eq!(
+            env.parse_eval("(update-in {1 [5]} [1 0] (fn [x y] (- x y)) 1)"),
+            parse(&mut env, "{1 [4]}")
+        );
+    }
+
+    #[test]
+    fn keys_and_vals() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(keys {2 3 1 4})"), parse(&mut env, "[1 2]"));
+        assert_eq!(env.parse_eval("(vals {2 3 1 4})"), parse(&mut env, "[4 3]"));
+        assert_eq!(env.parse_eval("(keys nil)"), parse(&mut env, "[]"));
+        assert_eq!(
+            env.parse_eval("(vals [1])").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn contains() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(contains? {1 nil} 1)"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.parse_eval("(contains? {1 2} 2)"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(contains? [5 6] 1)"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.parse_eval("(contains? nil 1)"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(contains? 1 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn merge() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(merge {1 2 3 4} nil {3 5 6 7})"),
+            parse(&mut env, "{1 2 3 5 6 7}")
+        );
+        assert_eq!(env.parse_eval("(merge)"), Ok(Expression::Nil));
+        assert_eq!(env.parse_eval("(merge nil nil)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.parse_eval("(merge {} [])").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+}
@@ -1,11 +1,17 @@
 pub mod core;
+pub mod exception;
+pub mod map;
 pub mod operators;
+pub mod seq;
+pub mod set;
+pub mod string;
 
 /// Macro to make a builtin function with one mandatory argument 'x'.
 ///
 /// This macro expects three arguments:
 ///  1) pub const name/identifier
 ///  2) closure of form |env, x| to be executed
+///
 /// 'env' is the local environment
 #[macro_export]
 macro_rules! make_builtin__x {
@@ -25,6 +31,7 @@ macro_rules! make_builtin__x {
 /// This macro expects two arguments:
 ///  1) pub const name/identifier
 ///  2) closure of form |env, a, b| to be executed
+///
 /// 'env' is the local environment
 #[macro_export]
 macro_rules! make_builtin__a_and_b {
@@ -46,6 +53,7 @@ macro_rules! make_builtin__a_and_b {
 /// This macro expects two arguments:
 ///  1) pub const name/identifier
 ///  2) closure of form |env, a, b, c| to be executed
+///
 /// 'env' is the local environment
 #[macro_export]
 macro_rules! make_builtin__a_b_and_c {
@@ -69,6 +77,7 @@ macro_rules! make_builtin__a_b_and_c {
 ///  1) pub const name/identifier
 ///  2) closure of form |env| to be executed when no args are present
 ///  2) closure of form |env, args| to be executed with 1 or more args
+///
 /// 'env' is the local environment
 #[macro_export]
 macro_rules! make_builtin__args {
@@ -94,6 +103,7 @@ macro_rules! make_builtin__args {
 ///  1) name/identifier of this builtin
 ///  2) closure of form |env, x| to be executed when no 'ys' are received
 ///  3) closure of form |env, x, ys| to be executed `x` & `ys` are present
+///
 /// 'env' is the local environment
 #[macro_export]
 macro_rules! make_builtin__x_and_ys {
@@ -109,7 +119,7 @@ macro_rules! make_builtin__x_and_ys {
                         $x_only(env, x)
                     }
                 } else {
-                    Err(Error::UnexpectedArgs)
+                    Err(Error::new(ErrorKind::UnexpectedArgs))
                 }
             }),
         );
@@ -2,36 +2,148 @@ extern crate alloc;
 
 use crate::expression::FnBody;
 use crate::Environment;
-use crate::Error;
 use crate::Expression;
 use crate::{make_builtin__a_and_b, make_builtin__args, make_builtin__x, make_builtin__x_and_ys};
+use crate::{Error, ErrorKind};
 use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::mem::discriminant;
+
+// A numeric operand. Arithmetic stays in integers (and checks for overflow)
+// until a float is involved, at which point the result is promoted to a float.
+#[derive(Clone, Copy)]
+enum Num {
+    Int(i64),
+    Float(f64),
+}
+
+impl Num {
+    fn as_f64(self) -> f64 {
+        match self {
+            Num::Int(x) => x as f64,
+            Num::Float(x) => x,
+        }
+    }
+
+    fn is_nan(self) -> bool {
+        matches!(self, Num::Float(x) if x.is_nan())
+    }
+
+    // Apply a binary operation, using `int_op` if both operands are integers and
+    // `float_op` otherwise. `int_op` returns `None` on overflow or division by
+    // zero.
+    fn apply(
+        self,
+        other: Num,
+        int_op: fn(i64, i64) -> Option<i64>,
+        float_op: fn(f64, f64) -> f64,
+    ) -> Result<Num, Error> {
+        match (self, other) {
+            (Num::Int(a), Num::Int(b)) => int_op(a, b)
+                .map(Num::Int)
+                .ok_or(Error::new(ErrorKind::MathError)),
+            (a, b) => Ok(Num::Float(float_op(a.as_f64(), b.as_f64()))),
+        }
+    }
+
+    // Compare two numbers by value, regardless of type. NaN is not ordered
+    // relative to anything, including itself.
+    fn partial_cmp(self, other: Num) -> Option<Ordering> {
+        match (self, other) {
+            (Num::Int(a), Num::Int(b)) => Some(a.cmp(&b)),
+            (a, b) => a.as_f64().partial_cmp(&b.as_f64()),
+        }
+    }
+}
+
+impl TryFrom<Expression> for Num {
+    type Error = Error;
+
+    fn try_from(expr: Expression) -> Result<Self, Self::Error> {
+        match expr {
+            Expression::Number(x) => Ok(Num::Int(x)),
+            Expression::Float(x) => Ok(Num::Float(x)),
+            expr => Err(Error::new(ErrorKind::ImpossibleConversion)
+                .with_message("Expected a number.")
+                .with_value(&expr)),
+        }
+    }
+}
+
+impl From<Num> for Expression {
+    fn from(num: Num) -> Self {
+        match num {
+            Num::Int(x) => Expression::Number(x),
+            Num::Float(x) => Expression::Float(x),
+        }
+    }
+}
+
+// Evaluate an expression, which must produce a number.
+fn eval_num(env: &mut Environment, expr: Expression) -> Result<Num, Error> {
+    env.eval(expr)?.try_into()
+}
+
+// Compare two values. Numbers are compared by value, so that integers and
+// floats may be compared with each other. Other values may only be compared
+// with values of the same kind, and are compared structurally.
+fn compare(a: &Expression, b: &Expression) -> Option<Ordering> {
+    match (Num::try_from(a.clone()), Num::try_from(b.clone())) {
+        (Ok(a), Ok(b)) => a.partial_cmp(b),
+        _ if discriminant(a) == discriminant(b) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+// Evaluate each arg, and check that every adjacent pair of values satisfies
+// `test`.
+fn compare_chain(
+    env: &mut Environment,
+    x: Expression,
+    ys: Vec<Expression>,
+    test: fn(Option<Ordering>) -> bool,
+) -> Result<Expression, Error> {
+    Ok(Expression::Bool(
+        ys.into_iter()
+            .try_fold((true, env.eval(x)?), |acc, y| {
+                let y = env.eval(y)?;
+                Ok::<_, Error>((acc.0 && test(compare(&acc.1, &y)), y))
+            })?
+            .0,
+    ))
+}
 
 make_builtin__args!(
     ADD,
     |_env: &mut Environment| { Ok(Expression::Number(0)) },
     |env: &mut Environment, args: Vec<_>| {
-        Ok(Expression::Number(args.into_iter().try_fold(
-            0,
-            |acc: i64, data| {
-                acc.checked_add(env.eval(data)?.try_into()?)
-                    .ok_or(Error::MathError)
-            },
-        )?))
+        Ok(args
+            .into_iter()
+            .try_fold(Num::Int(0), |acc, data| {
+                acc.apply(eval_num(env, data)?, i64::checked_add, |a, b| a + b)
+            })?
+            .into())
     }
 );
 
 make_builtin__x_and_ys!(
     SUB,
-    |env: &mut Environment, x: Expression| Ok(-env.eval(x)?),
+    |env: &mut Environment, x: Expression| {
+        match eval_num(env, x)? {
+            Num::Int(x) => Ok(Expression::Number(
+                x.checked_neg().ok_or(ErrorKind::MathError)?,
+            )),
+            Num::Float(x) => Ok(Expression::Float(-x)),
+        }
+    },
     |env: &mut Environment, x: Expression, ys: Vec<_>| {
-        Ok(Expression::Number(ys.into_iter().try_fold(
-            env.eval(x)?.try_into()?,
-            |acc: i64, y| {
-                acc.checked_sub(env.eval(y)?.try_into()?)
-                    .ok_or(Error::MathError)
-            },
-        )?))
+        let x = eval_num(env, x)?;
+        Ok(ys
+            .into_iter()
+            .try_fold(x, |acc, y| {
+                acc.apply(eval_num(env, y)?, i64::checked_sub, |a, b| a - b)
+            })?
+            .into())
     }
 );
 
@@ -39,78 +151,89 @@ make_builtin__args!(
     MUL,
     |_env: &mut Environment| { Ok(Expression::Number(1)) },
     |env: &mut Environment, args: Vec<_>| {
-        Ok(Expression::Number(args.into_iter().try_fold(
-            1,
-            |acc: i64, data| {
-                acc.checked_mul(env.eval(data)?.try_into()?)
-                    .ok_or(Error::MathError)
-            },
-        )?))
+        Ok(args
+            .into_iter()
+            .try_fold(Num::Int(1), |acc, data| {
+                acc.apply(eval_num(env, data)?, i64::checked_mul, |a, b| a * b)
+            })?
+            .into())
     }
 );
 
+// Integer division truncates. Float division follows IEEE 754, so dividing a
+// float by zero produces an infinity rather than an error.
 make_builtin__x_and_ys!(
     DIV,
     |env: &mut Environment, x: Expression| {
-        Ok(Expression::Number(
-            1_i64
-                .checked_div(env.eval(x)?.try_into()?)
-                .ok_or(Error::MathError)?,
-        ))
+        Ok(Num::Int(1)
+            .apply(eval_num(env, x)?, i64::checked_div, |a, b| a / b)?
+            .into())
     },
     |env: &mut Environment, x: Expression, ys: Vec<_>| {
-        Ok(Expression::Number(ys.into_iter().try_fold(
-            env.eval(x)?.try_into()?,
-            |acc: i64, y| {
-                acc.checked_div(env.eval(y)?.try_into()?)
-                    .ok_or(Error::MathError)
-            },
-        )?))
+        let x = eval_num(env, x)?;
+        Ok(ys
+            .into_iter()
+            .try_fold(x, |acc, y| {
+                acc.apply(eval_num(env, y)?, i64::checked_div, |a, b| a / b)
+            })?
+            .into())
     }
 );
 
 make_builtin__a_and_b!(REM, |env: &mut Environment,
                              a: Expression,
                              b: Expression| {
-    let num: i64 = env.eval(a)?.try_into()?;
-    let div: i64 = env.eval(b)?.try_into()?;
-    Ok(Expression::Number(
-        num.checked_rem(div).ok_or(Error::MathError)?,
-    ))
+    let num = eval_num(env, a)?;
+    let div = eval_num(env, b)?;
+    Ok(num.apply(div, i64::checked_rem, |a, b| a % b)?.into())
 });
 
 make_builtin__x!(INC, |env: &mut Environment, x: Expression| {
-    let x: i64 = env.eval(x)?.try_into()?;
-    Ok(Expression::Number(x + 1))
+    Ok(eval_num(env, x)?
+        .apply(Num::Int(1), i64::checked_add, |a, b| a + b)?
+        .into())
 });
 
 make_builtin__x!(DEC, |env: &mut Environment, x: Expression| {
-    let x: i64 = env.eval(x)?.try_into()?;
-    Ok(Expression::Number(x - 1))
+    Ok(eval_num(env, x)?
+        .apply(Num::Int(1), i64::checked_sub, |a, b| a - b)?
+        .into())
 });
 
+// Find the greatest (or least) of the args, according to `pick`. The chosen
+// value keeps its type, and NaN is returned if any arg is NaN.
+fn extremum(
+    env: &mut Environment,
+    x: Expression,
+    ys: Vec<Expression>,
+    pick: Ordering,
+) -> Result<Expression, Error> {
+    let x = eval_num(env, x)?;
+    Ok(ys
+        .into_iter()
+        .try_fold(x, |acc, y| {
+            let y = eval_num(env, y)?;
+            Ok::<_, Error>(if acc.is_nan() {
+                acc
+            } else if y.is_nan() || y.partial_cmp(acc) == Some(pick) {
+                y
+            } else {
+                acc
+            })
+        })?
+        .into())
+}
+
 make_builtin__x_and_ys!(
     MAX,
-    |env: &mut Environment, x: Expression| { Ok(env.eval(x)?) },
-    |env: &mut Environment, x: Expression, mut ys: Vec<_>| {
-        ys.push(env.eval(x)?);
-        ys.into_iter()
-            .map(|expr| env.eval(expr).unwrap())
-            .max()
-            .ok_or(Error::TypeMismatch)
-    }
+    |env: &mut Environment, x: Expression| { Ok(eval_num(env, x)?.into()) },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| { extremum(env, x, ys, Ordering::Greater) }
 );
 
 make_builtin__x_and_ys!(
     MIN,
-    |env: &mut Environment, x: Expression| { Ok(env.eval(x)?) },
-    |env: &mut Environment, x: Expression, mut ys: Vec<_>| {
-        ys.push(env.eval(x)?);
-        ys.into_iter()
-            .map(|expr| env.eval(expr).unwrap())
-            .min()
-            .ok_or(Error::TypeMismatch)
-    }
+    |env: &mut Environment, x: Expression| { Ok(eval_num(env, x)?.into()) },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| { extremum(env, x, ys, Ordering::Less) }
 );
 
 make_builtin__x_and_ys!(
@@ -121,7 +244,13 @@ make_builtin__x_and_ys!(
             ys.into_iter()
                 .try_fold((true, env.eval(x)?), |acc, y| {
                     let y = env.eval(y)?;
-                    Ok((acc.0 && (acc.1 == y), y))
+                    // Numbers are equal if they have the same value, regardless of type. Per
+                    // IEEE 754, NaN is not equal to anything.
+                    let eq = match (Num::try_from(acc.1.clone()), Num::try_from(y.clone())) {
+                        (Ok(a), Ok(b)) => a.partial_cmp(b) == Some(Ordering::Equal),
+                        _ => acc.1 == y,
+                    };
+                    Ok::<_, Error>((acc.0 && eq, y))
                 })?
                 .0,
         ))
@@ -132,15 +261,7 @@ make_builtin__x_and_ys!(
     GT,
     |_env: &mut Environment, _x: Expression| { Ok(Expression::Bool(true)) },
     |env: &mut Environment, x: Expression, ys: Vec<_>| {
-        Ok(Expression::Bool(
-            ys.into_iter()
-                .rev()
-                .try_fold((true, env.eval(x)?), |acc, y| {
-                    let y = env.eval(y)?;
-                    Ok((acc.0 && (acc.1 > y), y))
-                })?
-                .0,
-        ))
+        compare_chain(env, x, ys, |o| o == Some(Ordering::Greater))
     }
 );
 
@@ -148,15 +269,9 @@ make_builtin__x_and_ys!(
     GTE,
     |_env: &mut Environment, _x: Expression| { Ok(Expression::Bool(true)) },
     |env: &mut Environment, x: Expression, ys: Vec<_>| {
-        Ok(Expression::Bool(
-            ys.into_iter()
-                .rev()
-                .try_fold((true, env.eval(x)?), |acc, y| {
-                    let y = env.eval(y)?;
-                    Ok((acc.0 && (acc.1 >= y), y))
-                })?
-                .0,
-        ))
+        compare_chain(env, x, ys, |o| {
+            matches!(o, Some(Ordering::Greater | Ordering::Equal))
+        })
     }
 );
 
@@ -164,15 +279,7 @@ make_builtin__x_and_ys!(
     LT,
     |_env: &mut Environment, _x: Expression| { Ok(Expression::Bool(true)) },
     |env: &mut Environment, x: Expression, ys: Vec<_>| {
-        Ok(Expression::Bool(
-            ys.into_iter()
-                .rev()
-                .try_fold((true, env.eval(x)?), |acc, y| {
-                    let y = env.eval(y)?;
-                    Ok((acc.0 && (acc.1 < y), y))
-                })?
-                .0,
-        ))
+        compare_chain(env, x, ys, |o| o == Some(Ordering::Less))
     }
 );
 
@@ -180,15 +287,9 @@ make_builtin__x_and_ys!(
     LTE,
     |_env: &mut Environment, _x: Expression| { Ok(Expression::Bool(true)) },
     |env: &mut Environment, x: Expression, ys: Vec<_>| {
-        Ok(Expression::Bool(
-            ys.into_iter()
-                .rev()
-                .try_fold((true, env.eval(x)?), |acc, y| {
-                    let y = env.eval(y)?;
-                    Ok((acc.0 && (acc.1 <= y), y))
-                })?
-                .0,
-        ))
+        compare_chain(env, x, ys, |o| {
+            matches!(o, Some(Ordering::Less | Ordering::Equal))
+        })
     }
 );
 
@@ -197,8 +298,8 @@ make_builtin__args!(
     |_env: &mut Environment| { Ok(Expression::Bool(true)) },
     |env: &mut Environment, args: Vec<_>| {
         Ok(Expression::Bool(
-            args.into_iter().fold(Ok(true), |acc, y| {
-                Ok(acc.unwrap() && env.eval(y)?.try_into()?)
+            args.into_iter().try_fold(true, |acc, y| {
+                Ok::<_, Error>(acc && env.eval(y)?.try_into()?)
             })?,
         ))
     }
@@ -209,8 +310,8 @@ make_builtin__args!(
     |_env: &mut Environment| { Ok(Expression::Bool(false)) },
     |env: &mut Environment, args: Vec<_>| {
         Ok(Expression::Bool(
-            args.into_iter().fold(Ok(false), |acc, y| {
-                Ok(acc.unwrap() || env.eval(y)?.try_into()?)
+            args.into_iter().try_fold(false, |acc, y| {
+                Ok::<_, Error>(acc || env.eval(y)?.try_into()?)
             })?,
         ))
     }
@@ -224,7 +325,7 @@ make_builtin__x!(NOT, |env: &mut Environment, x: Expression| {
 #[cfg(test)]
 mod tests {
     use crate::Environment;
-    use crate::Error;
+    use crate::ErrorKind;
     use crate::Expression;
 
     #[test]
@@ -238,6 +339,17 @@ mod tests {
             Ok(Expression::Number(3))
         );
         assert_eq!(env.parse_eval("(+)"), Ok(Expression::Number(0)));
+        assert_eq!(env.parse_eval("(+ 1 0.5)"), Ok(Expression::Float(1.5)));
+        assert_eq!(env.parse_eval("(+ 0.5 0.25)"), Ok(Expression::Float(0.75)));
+        assert_eq!(
+            env.parse_eval("(+ 9223372036854775807 1)")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::MathError)
+        );
+        assert_eq!(
+            env.parse_eval("(+ 1 nil)").map_err(|e| e.kind()),
+            Err(ErrorKind::ImpossibleConversion)
+        );
         assert_eq!(
             env.parse_eval("(let [a 9] (+ a 1))"),
             Ok(Expression::Number(10))
@@ -253,7 +365,12 @@ mod tests {
         assert_eq!(env.parse_eval("(- 1 200)"), Ok(Expression::Number(-199)));
         assert_eq!(env.parse_eval("(- 1 1 1)"), Ok(Expression::Number(-1)));
         assert_eq!(env.parse_eval("(- 10)"), Ok(Expression::Number(-10)));
-        assert_eq!(env.parse_eval("(- )"), Err(Error::TooFewArgs));
+        assert_eq!(env.parse_eval("(- 1.5)"), Ok(Expression::Float(-1.5)));
+        assert_eq!(env.parse_eval("(- 2 0.5)"), Ok(Expression::Float(1.5)));
+        assert_eq!(
+            env.parse_eval("(- )").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
         assert_eq!(
             env.parse_eval("(let [a 9] (- a 1))"),
             Ok(Expression::Number(8))
@@ -268,6 +385,7 @@ mod tests {
         assert_eq!(env.parse_eval("(* -10 2)"), Ok(Expression::Number(-20)));
         assert_eq!(env.parse_eval("(* -99)"), Ok(Expression::Number(-99)));
         assert_eq!(env.parse_eval("(*)"), Ok(Expression::Number(1)));
+        assert_eq!(env.parse_eval("(* 3 0.5)"), Ok(Expression::Float(1.5)));
         assert_eq!(
             env.parse_eval("(let [a 9] (* a 9))"),
             Ok(Expression::Number(81))
@@ -282,8 +400,20 @@ mod tests {
         assert_eq!(env.parse_eval("(/ -10 2)"), Ok(Expression::Number(-5)));
         assert_eq!(env.parse_eval("(/ -10 7)"), Ok(Expression::Number(-1)));
         assert_eq!(env.parse_eval("(/ 7)"), Ok(Expression::Number(1 / 7)));
-        assert_eq!(env.parse_eval("(/)"), Err(Error::TooFewArgs));
-        assert_eq!(env.parse_eval("(/ 1 0)"), Err(Error::MathError));
+        assert_eq!(
+            env.parse_eval("(/)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(/ 1 0)").map_err(|e| e.kind()),
+            Err(ErrorKind::MathError)
+        );
+        assert_eq!(env.parse_eval("(/ 7 2.0)"), Ok(Expression::Float(3.5)));
+        assert_eq!(env.parse_eval("(/ 4.0)"), Ok(Expression::Float(0.25)));
+        assert_eq!(
+            env.parse_eval("(/ 1.0 0)"),
+            Ok(Expression::Float(f64::INFINITY))
+        );
         assert_eq!(
             env.parse_eval("(let [a 9] (/ a 2))"),
             Ok(Expression::Number(4))
@@ -297,10 +427,23 @@ mod tests {
         assert_eq!(env.parse_eval("(rem 10 2)"), Ok(Expression::Number(0)));
         assert_eq!(env.parse_eval("(rem -10 2)"), Ok(Expression::Number(0)));
         assert_eq!(env.parse_eval("(rem -10 7)"), Ok(Expression::Number(-3)));
-        assert_eq!(env.parse_eval("(rem 1 2 3)"), Err(Error::TooManyArgs));
-        assert_eq!(env.parse_eval("(rem 1)"), Err(Error::TooFewArgs));
-        assert_eq!(env.parse_eval("(rem)"), Err(Error::TooFewArgs));
-        assert_eq!(env.parse_eval("(rem 1 0)"), Err(Error::MathError));
+        assert_eq!(
+            env.parse_eval("(rem 1 2 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(rem 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(rem)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(rem 1 0)").map_err(|e| e.kind()),
+            Err(ErrorKind::MathError)
+        );
+        assert_eq!(env.parse_eval("(rem 5.5 2)"), Ok(Expression::Float(1.5)));
         assert_eq!(
             env.parse_eval("(let [a 9] (rem a 2))"),
             Ok(Expression::Number(1))
@@ -313,8 +456,15 @@ mod tests {
         env.load_default_builtins().unwrap();
         assert_eq!(env.parse_eval("(inc 10)"), Ok(Expression::Number(11)));
         assert_eq!(env.parse_eval("(inc -10)"), Ok(Expression::Number(-9)));
-        assert_eq!(env.parse_eval("(inc 1 2)"), Err(Error::TooManyArgs));
-        assert_eq!(env.parse_eval("(inc )"), Err(Error::TooFewArgs));
+        assert_eq!(env.parse_eval("(inc 0.5)"), Ok(Expression::Float(1.5)));
+        assert_eq!(
+            env.parse_eval("(inc 1 2)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(inc )").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
         assert_eq!(
             env.parse_eval("(let [a 9] (inc a))"),
             Ok(Expression::Number(10))
@@ -327,8 +477,15 @@ mod tests {
         env.load_default_builtins().unwrap();
         assert_eq!(env.parse_eval("(dec 10)"), Ok(Expression::Number(9)));
         assert_eq!(env.parse_eval("(dec -10)"), Ok(Expression::Number(-11)));
-        assert_eq!(env.parse_eval("(dec 1 2)"), Err(Error::TooManyArgs));
-        assert_eq!(env.parse_eval("(dec )"), Err(Error::TooFewArgs));
+        assert_eq!(env.parse_eval("(dec 0.5)"), Ok(Expression::Float(-0.5)));
+        assert_eq!(
+            env.parse_eval("(dec 1 2)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(dec )").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
         assert_eq!(
             env.parse_eval("(let [a 9] (dec a))"),
             Ok(Expression::Number(8))
@@ -341,7 +498,20 @@ mod tests {
         env.load_default_builtins().unwrap();
         assert_eq!(env.parse_eval("(max 8 6 -1 9)"), Ok(Expression::Number(9)));
         assert_eq!(env.parse_eval("(max 88)"), Ok(Expression::Number(88)));
-        assert_eq!(env.parse_eval("(max)"), Err(Error::TooFewArgs));
+        assert_eq!(env.parse_eval("(max 1 2.5 2)"), Ok(Expression::Float(2.5)));
+        assert_eq!(env.parse_eval("(max 1.5 3)"), Ok(Expression::Number(3)));
+        assert_eq!(
+            env.parse_eval("(max 1 ##NaN 2)"),
+            Ok(Expression::Float(f64::NAN))
+        );
+        assert_eq!(
+            env.parse_eval("(max 1 nil)").map_err(|e| e.kind()),
+            Err(ErrorKind::ImpossibleConversion)
+        );
+        assert_eq!(
+            env.parse_eval("(max)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
         assert_eq!(
             env.parse_eval("(let [a 9] (max 8 a))"),
             Ok(Expression::Number(9))
@@ -354,7 +524,15 @@ mod tests {
         env.load_default_builtins().unwrap();
         assert_eq!(env.parse_eval("(min 8 6 -1 9)"), Ok(Expression::Number(-1)));
         assert_eq!(env.parse_eval("(min 88)"), Ok(Expression::Number(88)));
-        assert_eq!(env.parse_eval("(min)"), Err(Error::TooFewArgs));
+        assert_eq!(env.parse_eval("(min 1 0.5 2)"), Ok(Expression::Float(0.5)));
+        assert_eq!(
+            env.parse_eval("(min ##NaN 1)"),
+            Ok(Expression::Float(f64::NAN))
+        );
+        assert_eq!(
+            env.parse_eval("(min)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
         assert_eq!(
             env.parse_eval("(let [a 9] (min a 10))"),
             Ok(Expression::Number(9))
@@ -368,7 +546,16 @@ mod tests {
         assert_eq!(env.parse_eval("(== 8 8 8)"), Ok(Expression::Bool(true)),);
         assert_eq!(env.parse_eval("(== 8 6 8)"), Ok(Expression::Bool(false)),);
         assert_eq!(env.parse_eval("(== 8)"), Ok(Expression::Bool(true)));
-        assert_eq!(env.parse_eval("(==)"), Err(Error::TooFewArgs));
+        assert_eq!(env.parse_eval("(== 2 2.0)"), Ok(Expression::Bool(true)));
+        assert_eq!(env.parse_eval("(== 2 2.5)"), Ok(Expression::Bool(false)));
+        assert_eq!(
+            env.parse_eval("(== ##NaN ##NaN)"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(==)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
         assert_eq!(
             env.parse_eval("(let [a 9] (== a 9))"),
             Ok(Expression::Bool(true))
@@ -382,7 +569,12 @@ mod tests {
         assert_eq!(env.parse_eval("(> 3 2 1)"), Ok(Expression::Bool(true),));
         assert_eq!(env.parse_eval("(> 3 2 1 2)"), Ok(Expression::Bool(false)),);
         assert_eq!(env.parse_eval("(> 2)"), Ok(Expression::Bool(true),));
-        assert_eq!(env.parse_eval("(>)"), Err(Error::TooFewArgs));
+        assert_eq!(env.parse_eval("(> 2.5 2 1.5)"), Ok(Expression::Bool(true)));
+        assert_eq!(env.parse_eval("(> ##NaN 1)"), Ok(Expression::Bool(false)));
+        assert_eq!(
+            env.parse_eval("(>)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
         assert_eq!(
             env.parse_eval("(let [a 9] (> a 8))"),
             Ok(Expression::Bool(true))
@@ -396,7 +588,11 @@ mod tests {
         assert_eq!(env.parse_eval("(>= 3 2 2 1)"), Ok(Expression::Bool(true)),);
         assert_eq!(env.parse_eval("(>= 3 2 1 2)"), Ok(Expression::Bool(false)),);
         assert_eq!(env.parse_eval("(>= 2)"), Ok(Expression::Bool(true)));
-        assert_eq!(env.parse_eval("(>=)"), Err(Error::TooFewArgs));
+        assert_eq!(env.parse_eval("(>= 2 2.0 1)"), Ok(Expression::Bool(true)));
+        assert_eq!(
+            env.parse_eval("(>=)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
         assert_eq!(
             env.parse_eval("(let [a 9] (>= a 9))"),
             Ok(Expression::Bool(true))
@@ -410,7 +606,20 @@ mod tests {
         assert_eq!(env.parse_eval("(< 1 2 3)"), Ok(Expression::Bool(true),));
         assert_eq!(env.parse_eval("(< 1 2 1 3)"), Ok(Expression::Bool(false)),);
         assert_eq!(env.parse_eval("(< 2)"), Ok(Expression::Bool(true)));
-        assert_eq!(env.parse_eval("(<)"), Err(Error::TooFewArgs));
+        assert_eq!(
+            env.parse_eval("(< ##-Inf -1 0.5 1 ##Inf)"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(env.parse_eval("(< 1 ##NaN)"), Ok(Expression::Bool(false)));
+        assert_eq!(
+            env.parse_eval("(< \"a\" \"b\")"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(env.parse_eval("(< 1 \"b\")"), Ok(Expression::Bool(false)));
+        assert_eq!(
+            env.parse_eval("(<)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
         assert_eq!(
             env.parse_eval("(let [a 9] (< a 10))"),
             Ok(Expression::Bool(true))
@@ -427,7 +636,14 @@ mod tests {
             Ok(Expression::Bool(false)),
         );
         assert_eq!(env.parse_eval("(<= 2)"), Ok(Expression::Bool(true)));
-        assert_eq!(env.parse_eval("(<=)"), Err(Error::TooFewArgs));
+        assert_eq!(
+            env.parse_eval("(<= ##NaN ##NaN)"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(<=)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
         assert_eq!(
             env.parse_eval("(let [a 9] (<= a 9))"),
             Ok(Expression::Bool(true))
@@ -482,8 +698,14 @@ mod tests {
         env.load_default_builtins().unwrap();
         assert_eq!(env.parse_eval("(not true)"), Ok(Expression::Bool(false)),);
         assert_eq!(env.parse_eval("(not false)"), Ok(Expression::Bool(true)),);
-        assert_eq!(env.parse_eval("(not true true)"), Err(Error::TooManyArgs));
-        assert_eq!(env.parse_eval("(not)"), Err(Error::TooFewArgs));
+        assert_eq!(
+            env.parse_eval("(not true true)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(not)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
         assert_eq!(
             env.parse_eval("(let [a true] (not a))"),
             Ok(Expression::Bool(false))
@@ -0,0 +1,547 @@
+extern crate alloc;
+
+use crate::builtins::core::conj;
+use crate::expression::FnBody;
+use crate::Environment;
+use crate::Expression;
+use crate::{make_builtin__a_and_b, make_builtin__x, make_builtin__x_and_ys};
+use crate::{Error, ErrorKind};
+use alloc::collections::BTreeMap;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+// The items of a collection, in order. Maps produce `[k v]` pairs, strings
+// produce their characters, and `nil` has no items.
+pub(crate) fn items(coll: Expression) -> Result<Vec<Expression>, Error> {
+    match coll {
+        Expression::List(v) | Expression::Vector(v) => Ok(v),
+        Expression::Set(s) => Ok(s.into_iter().collect()),
+        Expression::Map(m) => Ok(m
+            .into_iter()
+            .map(|(k, v)| Expression::Vector(vec![k, v]))
+            .collect()),
+        Expression::String(s) => Ok(s
+            .chars()
+            .map(|c| Expression::String(c.to_string()))
+            .collect()),
+        Expression::Nil => Ok(Vec::new()),
+        coll => Err(Error::new(ErrorKind::TypeMismatch)
+            .with_message("Expected a collection.")
+            .with_value(&coll)),
+    }
+}
+
+// Evaluate an expression, which must produce a collection, and return its
+// items.
+fn eval_items(env: &mut Environment, expr: Expression) -> Result<Vec<Expression>, Error> {
+    let coll = env.eval(expr)?;
+    items(coll)
+}
+
+// Evaluate an expression, which must produce a count of items.
+fn eval_count(env: &mut Environment, expr: Expression) -> Result<usize, Error> {
+    let n: i64 = env.eval(expr)?.try_into()?;
+    Ok(usize::try_from(n).unwrap_or(0))
+}
+
+// Call a predicate with an item, and return whether the result is true.
+fn test(env: &mut Environment, pred: &Expression, item: Expression) -> Result<bool, Error> {
+    Ok(env.apply(pred.clone(), vec![item])?.is_truthy())
+}
+
+// `(map f coll)` calls `f` with each item. Given several collections, `f` is
+// called with an item from each, stopping at the end of the shortest.
+make_builtin__x_and_ys!(
+    MAP,
+    |_env: &mut Environment, _x: Expression| { Err(Error::new(ErrorKind::TooFewArgs)) },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| {
+        let f = env.eval(x)?;
+        let mut colls: Vec<_> = ys
+            .into_iter()
+            .map(|y| eval_items(env, y).map(Vec::into_iter))
+            .try_collect()?;
+        let mut res = Vec::new();
+        loop {
+            let args: Option<Vec<_>> = colls.iter_mut().map(Iterator::next).collect();
+            match args {
+                Some(args) => res.push(env.apply(f.clone(), args)?),
+                None => return Ok(Expression::List(res)),
+            }
+        }
+    }
+);
+
+make_builtin__a_and_b!(FILTER, |env: &mut Environment,
+                                a: Expression,
+                                b: Expression| {
+    let pred = env.eval(a)?;
+    let mut res = Vec::new();
+    for item in eval_items(env, b)? {
+        if test(env, &pred, item.clone())? {
+            res.push(item);
+        }
+    }
+    Ok(Expression::List(res))
+});
+
+make_builtin__a_and_b!(REMOVE, |env: &mut Environment,
+                                a: Expression,
+                                b: Expression| {
+    let pred = env.eval(a)?;
+    let mut res = Vec::new();
+    for item in eval_items(env, b)? {
+        if !test(env, &pred, item.clone())? {
+            res.push(item);
+        }
+    }
+    Ok(Expression::List(res))
+});
+
+// `(reduce f coll)` or `(reduce f init coll)`. Without an initial value, the
+// first item is used, and reducing an empty collection calls `f` with no args.
+make_builtin__x_and_ys!(
+    REDUCE,
+    |_env: &mut Environment, _x: Expression| { Err(Error::new(ErrorKind::TooFewArgs)) },
+    |env: &mut Environment, x: Expression, mut ys: Vec<_>| {
+        let f = env.eval(x)?;
+        let (init, coll) = match ys.len() {
+            1 => (None, eval_items(env, ys.remove(0))?),
+            2 => {
+                let init = env.eval(ys.remove(0))?;
+                (Some(init), eval_items(env, ys.remove(0))?)
+            }
+            _ => return Err(Error::new(ErrorKind::TooManyArgs)),
+        };
+        let mut coll = coll.into_iter();
+        let init = match init.or_else(|| coll.next()) {
+            Some(init) => init,
+            None => return env.apply(f, Vec::new()),
+        };
+        coll.try_fold(init, |acc, item| env.apply(f.clone(), vec![acc, item]))
+    }
+);
+
+make_builtin__x!(FIRST, |env: &mut Environment, x: Expression| {
+    Ok(eval_items(env, x)?
+        .into_iter()
+        .next()
+        .unwrap_or(Expression::Nil))
+});
+
+make_builtin__x!(REST, |env: &mut Environment, x: Expression| {
+    Ok(Expression::List(
+        eval_items(env, x)?.into_iter().skip(1).collect(),
+    ))
+});
+
+make_builtin__a_and_b!(CONS, |env: &mut Environment,
+                              a: Expression,
+                              b: Expression| {
+    let item = env.eval(a)?;
+    let mut items = eval_items(env, b)?;
+    items.insert(0, item);
+    Ok(Expression::List(items))
+});
+
+make_builtin__x!(IS_EMPTY, |env: &mut Environment, x: Expression| {
+    Ok(Expression::Bool(eval_items(env, x)?.is_empty()))
+});
+
+make_builtin__a_and_b!(TAKE, |env: &mut Environment,
+                              a: Expression,
+                              b: Expression| {
+    let n = eval_count(env, a)?;
+    Ok(Expression::List(
+        eval_items(env, b)?.into_iter().take(n).collect(),
+    ))
+});
+
+make_builtin__a_and_b!(DROP, |env: &mut Environment,
+                              a: Expression,
+                              b: Expression| {
+    let n = eval_count(env, a)?;
+    Ok(Expression::List(
+        eval_items(env, b)?.into_iter().skip(n).collect(),
+    ))
+});
+
+make_builtin__x!(REVERSE, |env: &mut Environment, x: Expression| {
+    Ok(Expression::List(
+        eval_items(env, x)?.into_iter().rev().collect(),
+    ))
+});
+
+// `(range end)`, `(range start end)` or `(range start end step)` counts from
+// `start` (default 0) up to, but not including, `end`. A negative step counts
+// down instead.
+make_builtin__x_and_ys!(
+    RANGE,
+    |env: &mut Environment, x: Expression| {
+        let end: i64 = env.eval(x)?.try_into()?;
+        Ok(Expression::List((0..end).map(Expression::Number).collect()))
+    },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| {
+        let start: i64 = env.eval(x)?.try_into()?;
+        let mut ys = ys.into_iter();
+        let end: i64 = env.eval(ys.next().unwrap())?.try_into()?;
+        let step: i64 = match ys.next() {
+            Some(step) => env.eval(step)?.try_into()?,
+            None => 1,
+        };
+        if ys.next().is_some() {
+            return Err(Error::new(ErrorKind::TooManyArgs));
+        } else if step == 0 {
+            return Err(
+                Error::new(ErrorKind::MathError).with_message("The step of a range cannot be 0.")
+            );
+        }
+        let mut res = Vec::new();
+        let mut n = start;
+        while (step > 0 && n < end) || (step < 0 && n > end) {
+            res.push(Expression::Number(n));
+            n = match n.checked_add(step) {
+                Some(n) => n,
+                None => break,
+            };
+        }
+        Ok(Expression::List(res))
+    }
+);
+
+// `(some pred coll)` produces the first true result of `pred`, or `nil`.
+make_builtin__a_and_b!(SOME, |env: &mut Environment,
+                              a: Expression,
+                              b: Expression| {
+    let pred = env.eval(a)?;
+    for item in eval_items(env, b)? {
+        let res = env.apply(pred.clone(), vec![item])?;
+        if res.is_truthy() {
+            return Ok(res);
+        }
+    }
+    Ok(Expression::Nil)
+});
+
+make_builtin__a_and_b!(IS_EVERY, |env: &mut Environment,
+                                  a: Expression,
+                                  b: Expression| {
+    let pred = env.eval(a)?;
+    for item in eval_items(env, b)? {
+        if !test(env, &pred, item)? {
+            return Ok(Expression::Bool(false));
+        }
+    }
+    Ok(Expression::Bool(true))
+});
+
+// `(into to from)` adds each item of `from` to `to`, as `conj` does.
+make_builtin__a_and_b!(INTO, |env: &mut Environment,
+                              a: Expression,
+                              b: Expression| {
+    let mut coll = env.eval(a)?;
+    for item in eval_items(env, b)? {
+        conj(&mut coll, item)?;
+    }
+    Ok(coll)
+});
+
+// `(partition n coll)` or `(partition n step coll)` splits a collection into
+// lists of `n` items, each starting `step` (default `n`) items after the last.
+// Any items left over at the end which cannot fill a partition are dropped.
+make_builtin__x_and_ys!(
+    PARTITION,
+    |_env: &mut Environment, _x: Expression| { Err(Error::new(ErrorKind::TooFewArgs)) },
+    |env: &mut Environment, x: Expression, mut ys: Vec<_>| {
+        let n = eval_count(env, x)?;
+        let (step, coll) = match ys.len() {
+            1 => (n, ys.remove(0)),
+            2 => (eval_count(env, ys.remove(0))?, ys.remove(0)),
+            _ => return Err(Error::new(ErrorKind::TooManyArgs)),
+        };
+        if n == 0 || step == 0 {
+            return Err(Error::new(ErrorKind::OutOfRange)
+                .with_message("Partitions must hold at least 1 item."));
+        }
+        let items = eval_items(env, coll)?;
+        let mut res = Vec::new();
+        let mut start = 0;
+        while start + n <= items.len() {
+            res.push(Expression::List(items[start..start + n].to_vec()));
+            start += step;
+        }
+        Ok(Expression::List(res))
+    }
+);
+
+// `(group-by f coll)` makes a map from each result of `f` to a vector of the
+// items which produced it, in order.
+make_builtin__a_and_b!(GROUP_BY, |env: &mut Environment,
+                                  a: Expression,
+                                  b: Expression| {
+    let f = env.eval(a)?;
+    let mut groups: BTreeMap<Expression, Vec<Expression>> = BTreeMap::new();
+    for item in eval_items(env, b)? {
+        let key = env.apply(f.clone(), vec![item.clone()])?;
+        groups.entry(key).or_default().push(item);
+    }
+    Ok(Expression::Map(
+        groups
+            .into_iter()
+            .map(|(key, group)| (key, Expression::Vector(group)))
+            .collect(),
+    ))
+});
+
+make_builtin__x!(FREQUENCIES, |env: &mut Environment, x: Expression| {
+    let mut counts: BTreeMap<Expression, i64> = BTreeMap::new();
+    for item in eval_items(env, x)? {
+        *counts.entry(item).or_insert(0) += 1;
+    }
+    Ok(Expression::Map(
+        counts
+            .into_iter()
+            .map(|(item, n)| (item, Expression::Number(n)))
+            .collect(),
+    ))
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::Environment;
+    use crate::Expression;
+    use crate::{Error, ErrorKind};
+    use alloc::format;
+
+    fn parse(env: &mut Environment, s: &str) -> Result<Expression, Error> {
+        env.parse_eval(&format!("(quote {})", s))
+    }
+
+    #[test]
+    fn map() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        let expected = parse(&mut env, "(2 3 4)");
+        assert_eq!(env.parse_eval("(map inc [1 2 3])"), expected);
+        assert_eq!(env.parse_eval("(map (fn [x] (+ x 1)) '(1 2 3))"), expected);
+        assert_eq!(
+            env.parse_eval("(map + [1 2 3] [10 20])"),
+            parse(&mut env, "(11 22)")
+        );
+        assert_eq!(
+            env.parse_eval("(map :a [{:a 1} {:a 2} {}])"),
+            parse(&mut env, "(1 2 nil)")
+        );
+        assert_eq!(env.parse_eval("(map inc nil)"), parse(&mut env, "()"));
+        assert_eq!(
+            env.parse_eval("(map first {:a 1 :b 2})"),
+            parse(&mut env, "(:a :b)")
+        );
+        assert_eq!(
+            env.parse_eval("(map inc)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(map 1 [1])").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedFunction)
+        );
+        assert_eq!(
+            env.parse_eval("(map inc 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn filter() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        env.parse_eval("(defn even? [n] (== 0 (rem n 2)))").unwrap();
+        assert_eq!(
+            env.parse_eval("(filter even? (range 7))"),
+            parse(&mut env, "(0 2 4 6)")
+        );
+        assert_eq!(
+            env.parse_eval("(remove even? (range 7))"),
+            parse(&mut env, "(1 3 5)")
+        );
+        assert_eq!(
+            env.parse_eval("(filter :ok [{:ok true} {} {:ok 1}])"),
+            parse(&mut env, "({:ok true} {:ok 1})")
+        );
+    }
+
+    #[test]
+    fn reduce() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(reduce + [1 2 3 4])"),
+            Ok(Expression::Number(10))
+        );
+        assert_eq!(
+            env.parse_eval("(reduce + 100 [1 2 3 4])"),
+            Ok(Expression::Number(110))
+        );
+        assert_eq!(env.parse_eval("(reduce + [])"), Ok(Expression::Number(0)));
+        assert_eq!(env.parse_eval("(reduce + 5 [])"), Ok(Expression::Number(5)));
+        assert_eq!(
+            env.parse_eval("(reduce (fn [acc x] (conj acc (* x x))) [] [1 2 3])"),
+            parse(&mut env, "[1 4 9]")
+        );
+        assert_eq!(
+            env.parse_eval("(reduce + 1 2 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+    }
+
+    #[test]
+    fn first_and_rest() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(first [1 2])"), Ok(Expression::Number(1)));
+        assert_eq!(env.parse_eval("(first [])"), Ok(Expression::Nil));
+        assert_eq!(env.parse_eval("(first nil)"), Ok(Expression::Nil));
+        assert_eq!(env.parse_eval("(rest [1 2 3])"), parse(&mut env, "(2 3)"));
+        assert_eq!(env.parse_eval("(rest nil)"), parse(&mut env, "()"));
+        assert_eq!(env.parse_eval("(cons 0 [1 2])"), parse(&mut env, "(0 1 2)"));
+        assert_eq!(env.parse_eval("(cons 0 nil)"), parse(&mut env, "(0)"));
+        assert_eq!(env.parse_eval("(empty? [])"), Ok(Expression::Bool(true)));
+        assert_eq!(env.parse_eval("(empty? nil)"), Ok(Expression::Bool(true)));
+        assert_eq!(
+            env.parse_eval("(empty? \"a\")"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(empty? {:a 1})"),
+            Ok(Expression::Bool(false))
+        );
+    }
+
+    #[test]
+    fn take_and_drop() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(take 2 [1 2 3])"), parse(&mut env, "(1 2)"));
+        assert_eq!(
+            env.parse_eval("(take 5 [1 2 3])"),
+            parse(&mut env, "(1 2 3)")
+        );
+        assert_eq!(env.parse_eval("(take -1 [1 2 3])"), parse(&mut env, "()"));
+        assert_eq!(env.parse_eval("(drop 2 [1 2 3])"), parse(&mut env, "(3)"));
+        assert_eq!(
+            env.parse_eval("(reverse [1 2 3])"),
+            parse(&mut env, "(3 2 1)")
+        );
+        assert_eq!(
+            env.parse_eval("(take :a [1])").map_err(|e| e.kind()),
+            Err(ErrorKind::ImpossibleConversion)
+        );
+    }
+
+    #[test]
+    fn range() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(range 3)"), parse(&mut env, "(0 1 2)"));
+        assert_eq!(env.parse_eval("(range -1)"), parse(&mut env, "()"));
+        assert_eq!(env.parse_eval("(range 2 5)"), parse(&mut env, "(2 3 4)"));
+        assert_eq!(
+            env.parse_eval("(range 0 10 3)"),
+            parse(&mut env, "(0 3 6 9)")
+        );
+        assert_eq!(env.parse_eval("(range 3 0 -1)"), parse(&mut env, "(3 2 1)"));
+        assert_eq!(
+            env.parse_eval("(range 0 3 0)").map_err(|e| e.kind()),
+            Err(ErrorKind::MathError)
+        );
+        assert_eq!(
+            env.parse_eval("(range 0 3 1 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+    }
+
+    #[test]
+    fn some_and_every() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(some :id [{} {:id 4} {:id 5}])"),
+            Ok(Expression::Number(4))
+        );
+        assert_eq!(
+            env.parse_eval("(some (fn [x] (> x 5)) [1 2])"),
+            Ok(Expression::Nil)
+        );
+        assert_eq!(
+            env.parse_eval("(every? (fn [x] (> x 0)) [1 2])"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.parse_eval("(every? (fn [x] (> x 1)) [1 2])"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(every? inc [])"),
+            Ok(Expression::Bool(true))
+        );
+    }
+
+    #[test]
+    fn into() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(into [] (range 3))"),
+            parse(&mut env, "[0 1 2]")
+        );
+        assert_eq!(
+            env.parse_eval("(into #{} [1 1 2])"),
+            parse(&mut env, "#{1 2}")
+        );
+        assert_eq!(
+            env.parse_eval("(into {} [[:a 1] [:b 2]])"),
+            parse(&mut env, "{:a 1 :b 2}")
+        );
+        assert_eq!(
+            env.parse_eval("(into '(1) [2 3])"),
+            parse(&mut env, "(3 2 1)")
+        );
+        assert_eq!(
+            env.parse_eval("(into 1 [2])").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn partition() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(partition 2 (range 5))"),
+            parse(&mut env, "((0 1) (2 3))")
+        );
+        assert_eq!(
+            env.parse_eval("(partition 2 1 [1 2 3])"),
+            parse(&mut env, "((1 2) (2 3))")
+        );
+        assert_eq!(
+            env.parse_eval("(partition 0 [1])").map_err(|e| e.kind()),
+            Err(ErrorKind::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn group_by_and_frequencies() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(group-by count [\"a\" \"bc\" \"d\"])"),
+            parse(&mut env, "{1 [\"a\" \"d\"] 2 [\"bc\"]}")
+        );
+        assert_eq!(
+            env.parse_eval("(frequencies [:a :b :a])"),
+            parse(&mut env, "{:a 2 :b 1}")
+        );
+        assert_eq!(env.parse_eval("(frequencies nil)"), parse(&mut env, "{}"));
+    }
+}
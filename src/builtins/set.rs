@@ -0,0 +1,235 @@
+extern crate alloc;
+
+use crate::expression::FnBody;
+use crate::Environment;
+use crate::Expression;
+use crate::{make_builtin__a_and_b, make_builtin__args, make_builtin__x, make_builtin__x_and_ys};
+use crate::{Error, ErrorKind};
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+
+// Evaluate an expression, which must produce a set. `nil` is treated as an
+// empty set.
+fn eval_set(env: &mut Environment, expr: Expression) -> Result<BTreeSet<Expression>, Error> {
+    match env.eval(expr)? {
+        Expression::Set(s) => Ok(s),
+        Expression::Nil => Ok(BTreeSet::new()),
+        expr => Err(Error::new(ErrorKind::TypeMismatch)
+            .with_message("Expected a set.")
+            .with_value(&expr)),
+    }
+}
+
+// Build a set from the items of a collection.
+make_builtin__x!(SET, |env: &mut Environment, x: Expression| {
+    match env.eval(x)? {
+        Expression::Nil => Ok(Expression::Set(BTreeSet::new())),
+        Expression::List(v) | Expression::Vector(v) => Ok(Expression::Set(v.into_iter().collect())),
+        Expression::Set(s) => Ok(Expression::Set(s)),
+        _ => Err(Error::new(ErrorKind::TypeMismatch)),
+    }
+});
+
+make_builtin__x_and_ys!(
+    DISJ,
+    |env: &mut Environment, x: Expression| { Ok(Expression::Set(eval_set(env, x)?)) },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| {
+        let mut s = eval_set(env, x)?;
+        for y in ys {
+            s.remove(&env.eval(y)?);
+        }
+        Ok(Expression::Set(s))
+    }
+);
+
+make_builtin__args!(
+    UNION,
+    |_env: &mut Environment| { Ok(Expression::Set(BTreeSet::new())) },
+    |env: &mut Environment, args: Vec<_>| {
+        let mut union = BTreeSet::new();
+        for arg in args {
+            union.append(&mut eval_set(env, arg)?);
+        }
+        Ok(Expression::Set(union))
+    }
+);
+
+make_builtin__x_and_ys!(
+    INTERSECTION,
+    |env: &mut Environment, x: Expression| { Ok(Expression::Set(eval_set(env, x)?)) },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| {
+        let mut intersection = eval_set(env, x)?;
+        for y in ys {
+            let other = eval_set(env, y)?;
+            intersection.retain(|e| other.contains(e));
+        }
+        Ok(Expression::Set(intersection))
+    }
+);
+
+make_builtin__x_and_ys!(
+    DIFFERENCE,
+    |env: &mut Environment, x: Expression| { Ok(Expression::Set(eval_set(env, x)?)) },
+    |env: &mut Environment, x: Expression, ys: Vec<_>| {
+        let mut difference = eval_set(env, x)?;
+        for y in ys {
+            let other = eval_set(env, y)?;
+            difference.retain(|e| !other.contains(e));
+        }
+        Ok(Expression::Set(difference))
+    }
+);
+
+// `(subset? a b)` is true if every item of `a` is also in `b`.
+make_builtin__a_and_b!(SUBSET, |env: &mut Environment,
+                                a: Expression,
+                                b: Expression| {
+    let a = eval_set(env, a)?;
+    let b = eval_set(env, b)?;
+    Ok(Expression::Bool(a.is_subset(&b)))
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::Environment;
+    use crate::ErrorKind;
+    use crate::Expression;
+
+    #[test]
+    fn set_literal() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(let [a 1] #{a (+ a 1)})"),
+            env.parse_eval("(quote #{1 2})")
+        );
+        assert_eq!(
+            env.parse_eval("(== #{1 2 3} #{3 2 1})"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.parse_eval("(== #{1 2} #{1 3})"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(count #{1 2 2})").map_err(|e| e.kind()),
+            Err(ErrorKind::DuplicateKey)
+        );
+
+        // Items must be distinct once evaluated, not only as written
+        assert_eq!(
+            env.parse_eval("(let [a 1] #{a (+ a 1) 1})")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::DuplicateKey)
+        );
+    }
+
+    #[test]
+    fn set() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(set [:a :b :a])"),
+            env.parse_eval("(do #{:a :b})")
+        );
+        assert_eq!(env.parse_eval("(set nil)"), env.parse_eval("(do #{})"));
+        assert_eq!(
+            env.parse_eval("(set 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn conj_and_disj() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(conj #{1 2} 2 3)"),
+            env.parse_eval("(do #{1 2 3})")
+        );
+        assert_eq!(
+            env.parse_eval("(disj #{1 2 3} 2 4)"),
+            env.parse_eval("(do #{1 3})")
+        );
+        assert_eq!(env.parse_eval("(disj nil 1)"), env.parse_eval("(do #{})"));
+        assert_eq!(
+            env.parse_eval("(disj [1] 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn contains() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(contains? #{:a :b} :a)"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.parse_eval("(contains? #{:a :b} :c)"),
+            Ok(Expression::Bool(false))
+        );
+    }
+
+    #[test]
+    fn union() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(union #{1 2} nil #{2 3})"),
+            env.parse_eval("(do #{1 2 3})")
+        );
+        assert_eq!(env.parse_eval("(union)"), env.parse_eval("(do #{})"));
+        assert_eq!(
+            env.parse_eval("(union #{} [1])").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn intersection() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(intersection #{1 2 3} #{2 3 4} #{3 2})"),
+            env.parse_eval("(do #{2 3})")
+        );
+        assert_eq!(
+            env.parse_eval("(intersection #{1})"),
+            env.parse_eval("(do #{1})")
+        );
+        assert_eq!(
+            env.parse_eval("(intersection)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+    }
+
+    #[test]
+    fn difference() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(difference #{1 2 3 4} #{2} #{4 5})"),
+            env.parse_eval("(do #{1 3})")
+        );
+    }
+
+    #[test]
+    fn subset() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(subset? #{1 2} #{1 2 3})"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.parse_eval("(subset? #{1 4} #{1 2 3})"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(subset? #{} nil)"),
+            Ok(Expression::Bool(true))
+        );
+    }
+}
@@ -0,0 +1,351 @@
+extern crate alloc;
+
+use crate::expression::FnBody;
+use crate::Environment;
+use crate::Expression;
+use crate::{make_builtin__a_and_b, make_builtin__args, make_builtin__x, make_builtin__x_and_ys};
+use crate::{Error, ErrorKind};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+// Evaluate an expression, which must produce a string.
+fn eval_string(env: &mut Environment, expr: Expression) -> Result<String, Error> {
+    match env.eval(expr)? {
+        Expression::String(s) => Ok(s),
+        expr => Err(Error::new(ErrorKind::TypeMismatch)
+            .with_message("Expected a string.")
+            .with_value(&expr)),
+    }
+}
+
+// Evaluate an expression, which must produce a string index.
+fn eval_index(env: &mut Environment, expr: Expression) -> Result<usize, Error> {
+    let idx: i64 = env.eval(expr)?.try_into()?;
+    usize::try_from(idx).or(Err(Error::new(ErrorKind::OutOfRange)))
+}
+
+// The text of a value, as used by `str` and `join`. Strings are used as-is
+// (without quotes), `nil` is empty, and anything else is printed.
+fn text(expr: &Expression) -> String {
+    match expr {
+        Expression::String(s) => s.clone(),
+        Expression::Nil => String::new(),
+        _ => format!("{}", expr),
+    }
+}
+
+make_builtin__args!(
+    STR,
+    |_env: &mut Environment| { Ok(Expression::String(String::new())) },
+    |env: &mut Environment, args: Vec<_>| {
+        let mut s = String::new();
+        for arg in args {
+            s.push_str(&text(&env.eval(arg)?));
+        }
+        Ok(Expression::String(s))
+    }
+);
+
+make_builtin__x!(COUNT, |env: &mut Environment, x: Expression| {
+    match env.eval(x)? {
+        Expression::String(s) => Ok(Expression::Number(s.chars().count() as i64)),
+        Expression::List(v) | Expression::Vector(v) => Ok(Expression::Number(v.len() as i64)),
+        Expression::Map(m) => Ok(Expression::Number(m.len() as i64)),
+        Expression::Set(s) => Ok(Expression::Number(s.len() as i64)),
+        Expression::Nil => Ok(Expression::Number(0)),
+        expr => Err(Error::new(ErrorKind::TypeMismatch).with_value(&expr)),
+    }
+});
+
+// Substrings are indexed by character, not by byte.
+make_builtin__x_and_ys!(
+    SUBS,
+    |_env: &mut Environment, _x: Expression| { Err(Error::new(ErrorKind::TooFewArgs)) },
+    |env: &mut Environment, x: Expression, mut ys: Vec<_>| {
+        let s = eval_string(env, x)?;
+        let len = s.chars().count();
+        let (start, end) = match ys.len() {
+            1 => (eval_index(env, ys.remove(0))?, len),
+            2 => {
+                let start = eval_index(env, ys.remove(0))?;
+                (start, eval_index(env, ys.remove(0))?)
+            }
+            _ => return Err(Error::new(ErrorKind::TooManyArgs)),
+        };
+        if start > end || end > len {
+            return Err(Error::new(ErrorKind::OutOfRange));
+        }
+        Ok(Expression::String(
+            s.chars().skip(start).take(end - start).collect(),
+        ))
+    }
+);
+
+make_builtin__x!(UPPER_CASE, |env: &mut Environment, x: Expression| {
+    Ok(Expression::String(eval_string(env, x)?.to_uppercase()))
+});
+
+make_builtin__x!(LOWER_CASE, |env: &mut Environment, x: Expression| {
+    Ok(Expression::String(eval_string(env, x)?.to_lowercase()))
+});
+
+make_builtin__x!(TRIM, |env: &mut Environment, x: Expression| {
+    Ok(Expression::String(eval_string(env, x)?.trim().to_string()))
+});
+
+make_builtin__a_and_b!(SPLIT, |env: &mut Environment,
+                               a: Expression,
+                               b: Expression| {
+    let s = eval_string(env, a)?;
+    let sep = eval_string(env, b)?;
+    if sep.is_empty() {
+        // Split into individual characters
+        Ok(Expression::Vector(
+            s.chars()
+                .map(|c| Expression::String(c.to_string()))
+                .collect(),
+        ))
+    } else {
+        Ok(Expression::Vector(
+            s.split(sep.as_str())
+                .map(|part| Expression::String(part.to_string()))
+                .collect(),
+        ))
+    }
+});
+
+// Joins the items of a list or vector, with an optional separator:
+// `(join coll)` or `(join sep coll)`.
+make_builtin__x_and_ys!(
+    JOIN,
+    |env: &mut Environment, x: Expression| {
+        let items: Vec<_> = env
+            .eval(x)?
+            .try_into()
+            .or(Err(Error::new(ErrorKind::TypeMismatch)))?;
+        Ok(Expression::String(items.iter().map(text).collect()))
+    },
+    |env: &mut Environment, x: Expression, mut ys: Vec<_>| {
+        if ys.len() > 1 {
+            return Err(Error::new(ErrorKind::TooManyArgs));
+        }
+        let sep = eval_string(env, x)?;
+        let items: Vec<_> = env
+            .eval(ys.remove(0))?
+            .try_into()
+            .or(Err(Error::new(ErrorKind::TypeMismatch)))?;
+        Ok(Expression::String(
+            items.iter().map(text).collect::<Vec<_>>().join(&sep),
+        ))
+    }
+);
+
+make_builtin__a_and_b!(
+    STARTS_WITH,
+    |env: &mut Environment, a: Expression, b: Expression| {
+        let s = eval_string(env, a)?;
+        let prefix = eval_string(env, b)?;
+        Ok(Expression::Bool(s.starts_with(prefix.as_str())))
+    }
+);
+
+make_builtin__a_and_b!(INCLUDES, |env: &mut Environment,
+                                  a: Expression,
+                                  b: Expression| {
+    let s = eval_string(env, a)?;
+    let substr = eval_string(env, b)?;
+    Ok(Expression::Bool(s.contains(substr.as_str())))
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::Environment;
+    use crate::ErrorKind;
+    use crate::Expression;
+    use alloc::string::ToString;
+
+    fn string(s: &str) -> Expression {
+        Expression::String(s.to_string())
+    }
+
+    #[test]
+    fn str() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(str)"), Ok(string("")));
+        assert_eq!(env.parse_eval("(str \"a\")"), Ok(string("a")));
+        assert_eq!(
+            env.parse_eval("(str \"a\" 1 nil true \"b c\")"),
+            Ok(string("a1trueb c"))
+        );
+        assert_eq!(
+            env.parse_eval("(let [name \"World\"] (str \"Hello, \" name \"!\"))"),
+            Ok(string("Hello, World!"))
+        );
+    }
+
+    #[test]
+    fn count() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(count)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(count 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+        assert_eq!(env.parse_eval("(count \"\")"), Ok(Expression::Number(0)));
+        assert_eq!(
+            env.parse_eval("(count \"héllo\")"),
+            Ok(Expression::Number(5))
+        );
+        assert_eq!(env.parse_eval("(count [1 2 3])"), Ok(Expression::Number(3)));
+        assert_eq!(env.parse_eval("(count nil)"), Ok(Expression::Number(0)));
+        assert_eq!(
+            env.parse_eval("(count {1 2 3 4})"),
+            Ok(Expression::Number(2))
+        );
+    }
+
+    #[test]
+    fn subs() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(subs)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(subs \"hello\")").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(env.parse_eval("(subs \"hello\" 1)"), Ok(string("ello")));
+        assert_eq!(env.parse_eval("(subs \"hello\" 1 3)"), Ok(string("el")));
+        assert_eq!(env.parse_eval("(subs \"héllo\" 1 2)"), Ok(string("é")));
+        assert_eq!(env.parse_eval("(subs \"hello\" 5)"), Ok(string("")));
+        assert_eq!(
+            env.parse_eval("(subs \"hello\" 6)").map_err(|e| e.kind()),
+            Err(ErrorKind::OutOfRange)
+        );
+        assert_eq!(
+            env.parse_eval("(subs \"hello\" 3 2)").map_err(|e| e.kind()),
+            Err(ErrorKind::OutOfRange)
+        );
+        assert_eq!(
+            env.parse_eval("(subs \"hello\" -1)").map_err(|e| e.kind()),
+            Err(ErrorKind::OutOfRange)
+        );
+        assert_eq!(
+            env.parse_eval("(subs 5 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+        assert_eq!(
+            env.parse_eval("(subs \"hello\" 1 2 3)")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+    }
+
+    #[test]
+    fn case() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(upper-case \"Hello\")"),
+            Ok(string("HELLO"))
+        );
+        assert_eq!(
+            env.parse_eval("(lower-case \"Hello\")"),
+            Ok(string("hello"))
+        );
+        assert_eq!(
+            env.parse_eval("(upper-case 'a)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn trim() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(trim \"  a b \\n\")"), Ok(string("a b")));
+        assert_eq!(
+            env.parse_eval("(trim 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn split() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(split \"a,b,,c\" \",\")"),
+            env.parse_eval("(vector \"a\" \"b\" \"\" \"c\")")
+        );
+        assert_eq!(
+            env.parse_eval("(split \"abc\" \"\")"),
+            env.parse_eval("(vector \"a\" \"b\" \"c\")")
+        );
+        assert_eq!(
+            env.parse_eval("(split \"abc\")").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+    }
+
+    #[test]
+    fn join() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(env.parse_eval("(join [\"a\" 1 nil])"), Ok(string("a1")));
+        assert_eq!(
+            env.parse_eval("(join \", \" [\"a\" \"b\" 3])"),
+            Ok(string("a, b, 3"))
+        );
+        assert_eq!(env.parse_eval("(join \", \" [])"), Ok(string("")));
+        assert_eq!(
+            env.parse_eval("(join 5)").map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+        assert_eq!(
+            env.parse_eval("(join \",\" [] [])").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+    }
+
+    #[test]
+    fn starts_with() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(starts-with? \"hello\" \"he\")"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.parse_eval("(starts-with? \"hello\" \"lo\")"),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.parse_eval("(starts-with? \"hello\" 1)")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn includes() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(includes? \"hello\" \"ll\")"),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.parse_eval("(includes? \"hello\" \"x\")"),
+            Ok(Expression::Bool(false))
+        );
+    }
+}
@@ -0,0 +1,486 @@
+extern crate alloc;
+
+use crate::Environment;
+use crate::Expression;
+use crate::{Error, ErrorKind};
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::any::{type_name, Any};
+
+// Conversion from an expression to a Rust value, as used for the args of
+// functions registered with `Environment::register`.
+pub trait FromExpression: Sized {
+    fn from_expression(expr: Expression) -> Result<Self, Error>;
+}
+
+// Conversion from a Rust value to an expression, as used for the results of
+// functions registered with `Environment::register`.
+pub trait IntoExpression {
+    fn into_expression(self) -> Expression;
+}
+
+impl FromExpression for Expression {
+    fn from_expression(expr: Expression) -> Result<Self, Error> {
+        Ok(expr)
+    }
+}
+
+impl FromExpression for i64 {
+    fn from_expression(expr: Expression) -> Result<Self, Error> {
+        expr.try_into()
+    }
+}
+
+impl FromExpression for f64 {
+    fn from_expression(expr: Expression) -> Result<Self, Error> {
+        expr.try_into()
+    }
+}
+
+impl FromExpression for bool {
+    fn from_expression(expr: Expression) -> Result<Self, Error> {
+        expr.try_into()
+    }
+}
+
+impl FromExpression for String {
+    fn from_expression(expr: Expression) -> Result<Self, Error> {
+        match expr.unspanned() {
+            Expression::String(s) => Ok(s),
+            expr => Err(Error::new(ErrorKind::ImpossibleConversion)
+                .with_message("Expected a string.")
+                .with_value(&expr)),
+        }
+    }
+}
+
+// Lists and vectors convert item by item, and `nil` converts to an empty
+// vector.
+impl<T: FromExpression> FromExpression for Vec<T> {
+    fn from_expression(expr: Expression) -> Result<Self, Error> {
+        match expr.unspanned() {
+            Expression::List(v) | Expression::Vector(v) => {
+                v.into_iter().map(T::from_expression).try_collect()
+            }
+            Expression::Nil => Ok(Vec::new()),
+            expr => Err(Error::new(ErrorKind::ImpossibleConversion)
+                .with_message("Expected a list or vector.")
+                .with_value(&expr)),
+        }
+    }
+}
+
+// Userdata converts to a shared handle to the host value it holds, if that
+// value is of the expected type.
+impl<T: Any> FromExpression for Rc<T> {
+    fn from_expression(expr: Expression) -> Result<Self, Error> {
+        expr.downcast().ok_or_else(|| {
+            Error::new(ErrorKind::ImpossibleConversion)
+                .with_message(&format!(
+                    "Expected userdata of type `{}`.",
+                    type_name::<T>()
+                ))
+                .with_value(&expr)
+        })
+    }
+}
+
+// `nil` converts to `None`.
+impl<T: FromExpression> FromExpression for Option<T> {
+    fn from_expression(expr: Expression) -> Result<Self, Error> {
+        match expr.unspanned() {
+            Expression::Nil => Ok(None),
+            expr => T::from_expression(expr).map(Some),
+        }
+    }
+}
+
+impl IntoExpression for Expression {
+    fn into_expression(self) -> Expression {
+        self
+    }
+}
+
+impl IntoExpression for i64 {
+    fn into_expression(self) -> Expression {
+        Expression::Number(self)
+    }
+}
+
+impl IntoExpression for f64 {
+    fn into_expression(self) -> Expression {
+        Expression::Float(self)
+    }
+}
+
+impl IntoExpression for bool {
+    fn into_expression(self) -> Expression {
+        Expression::Bool(self)
+    }
+}
+
+impl IntoExpression for String {
+    fn into_expression(self) -> Expression {
+        Expression::String(self)
+    }
+}
+
+impl IntoExpression for &str {
+    fn into_expression(self) -> Expression {
+        Expression::String(self.to_string())
+    }
+}
+
+impl IntoExpression for () {
+    fn into_expression(self) -> Expression {
+        Expression::Nil
+    }
+}
+
+impl<T: IntoExpression> IntoExpression for Vec<T> {
+    fn into_expression(self) -> Expression {
+        Expression::Vector(self.into_iter().map(T::into_expression).collect())
+    }
+}
+
+impl<T: IntoExpression> IntoExpression for Option<T> {
+    fn into_expression(self) -> Expression {
+        match self {
+            Some(x) => x.into_expression(),
+            None => Expression::Nil,
+        }
+    }
+}
+
+// Tuples convert to and from vectors of the same length.
+macro_rules! tuple_conversions {
+    ($len:expr, $($item:ident),*) => {
+        impl<$($item: FromExpression),*> FromExpression for ($($item,)*) {
+            fn from_expression(expr: Expression) -> Result<Self, Error> {
+                match expr.unspanned() {
+                    Expression::List(v) | Expression::Vector(v) if v.len() == $len => {
+                        let mut items = v.into_iter();
+                        Ok(($($item::from_expression(items.next().unwrap())?,)*))
+                    }
+                    expr => Err(Error::new(ErrorKind::ImpossibleConversion)
+                        .with_message(&format!("Expected a vector of {} items.", $len))
+                        .with_value(&expr)),
+                }
+            }
+        }
+
+        impl<$($item: IntoExpression),*> IntoExpression for ($($item,)*) {
+            #[allow(non_snake_case)]
+            fn into_expression(self) -> Expression {
+                let ($($item,)*) = self;
+                Expression::Vector(alloc::vec![$($item.into_expression()),*])
+            }
+        }
+    };
+}
+
+tuple_conversions!(1, A);
+tuple_conversions!(2, A, B);
+tuple_conversions!(3, A, B, C);
+tuple_conversions!(4, A, B, C, D);
+
+// The args of a script function called from Rust with `Environment::call`:
+// a tuple of values which convert into expressions, or a vector of expressions.
+pub trait IntoArgs {
+    fn into_args(self) -> Vec<Expression>;
+}
+
+impl IntoArgs for Vec<Expression> {
+    fn into_args(self) -> Vec<Expression> {
+        self
+    }
+}
+
+macro_rules! into_args {
+    ($($arg:ident),*) => {
+        impl<$($arg: IntoExpression),*> IntoArgs for ($($arg,)*) {
+            #[allow(non_snake_case)]
+            fn into_args(self) -> Vec<Expression> {
+                let ($($arg,)*) = self;
+                alloc::vec![$($arg.into_expression()),*]
+            }
+        }
+    };
+}
+
+into_args!();
+into_args!(A);
+into_args!(A, B);
+into_args!(A, B, C);
+into_args!(A, B, C, D);
+into_args!(A, B, C, D, E);
+into_args!(A, B, C, D, E, G);
+
+// A Rust function which can be registered as a builtin, taking args of the
+// types in `Args`. Implemented for functions and closures of up to six args,
+// each of which converts from an expression, returning a result which converts
+// into an expression.
+pub trait NativeFunction<Args>: 'static {
+    // The number of args the function takes
+    const ARITY: usize;
+
+    fn call(&self, args: Vec<Expression>) -> Result<Expression, Error>;
+}
+
+macro_rules! native_function {
+    ($len:expr $(, $arg:ident)*) => {
+        impl<F, R, $($arg),*> NativeFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> Result<R, Error> + 'static,
+            R: IntoExpression,
+            $($arg: FromExpression,)*
+        {
+            const ARITY: usize = $len;
+
+            #[allow(unused_mut, unused_variables)]
+            fn call(&self, args: Vec<Expression>) -> Result<Expression, Error> {
+                let mut args = args.into_iter();
+                self($($arg::from_expression(args.next().unwrap())?),*)
+                    .map(IntoExpression::into_expression)
+            }
+        }
+    };
+}
+
+native_function!(0);
+native_function!(1, A);
+native_function!(2, A, B);
+native_function!(3, A, B, C);
+native_function!(4, A, B, C, D);
+native_function!(5, A, B, C, D, E);
+native_function!(6, A, B, C, D, E, G);
+
+impl Environment {
+    // Register a Rust function as a builtin. The function's args are evaluated
+    // and converted from expressions, and its result converted back, so that
+    // the function need not deal with the stack. Args which cannot be converted
+    // are reported as `ImpossibleConversion` errors, and the wrong number of
+    // args as `TooFewArgs` or `TooManyArgs` errors.
+    pub fn register<Args, F: NativeFunction<Args>>(
+        &mut self,
+        name: &str,
+        f: F,
+    ) -> Result<(), Error> {
+        fn params(arity: usize) -> Vec<String> {
+            (0..arity).map(|i| format!("arg{}", i)).collect()
+        }
+        let function = name.to_string();
+        let names = params(F::ARITY);
+        self.load_closure(name, &names.join(" "), move |env: &mut Environment| {
+            let args: Vec<_> = names
+                .iter()
+                .map(|name| env.pop_stack_if_named(name))
+                .try_collect()?;
+            let args = args.into_iter().map(|arg| env.eval(arg)).try_collect()?;
+            f.call(args).map_err(|e| e.with_function(&function))
+        })
+    }
+
+    // Call the function with the given name, as a script would, with args
+    // converted from Rust values. Names are resolved as at the head of a form, so
+    // globals shadow builtins.
+    pub fn call(&mut self, name: &str, args: impl IntoArgs) -> Result<Expression, Error> {
+        let func = self
+            .resolve(name)
+            .cloned()
+            .ok_or_else(|| Error::new(ErrorKind::DataNotFound).with_symbol(name))?;
+        if !func.is_function() {
+            return Err(Error::new(ErrorKind::ExpectedFunction)
+                .with_symbol(name)
+                .with_value(&func));
+        }
+        self.apply(func, args.into_args())
+    }
+
+    // Get the value bound to a name, as a script would see it, converted to a Rust
+    // value.
+    pub fn get_global<T: FromExpression>(&self, name: &str) -> Result<T, Error> {
+        let value = self
+            .find_data(name)
+            .or_else(|| self.find_builtin(name))
+            .cloned()
+            .ok_or_else(|| Error::new(ErrorKind::DataNotFound).with_symbol(name))?;
+        T::from_expression(value).map_err(|e| e.with_symbol(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FromExpression, IntoExpression};
+    use crate::Environment;
+    use crate::Expression;
+    use crate::{Error, ErrorKind};
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn conversions() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        let mut parse = |s: &str| env.parse_eval(s).unwrap();
+        assert_eq!(i64::from_expression(parse("(+ 1 2)")), Ok(3));
+        assert_eq!(f64::from_expression(parse("(do 2)")), Ok(2.0));
+        assert_eq!(
+            String::from_expression(parse("(str \"a\" 1)")),
+            Ok("a1".to_string())
+        );
+        assert_eq!(
+            Vec::<i64>::from_expression(parse("(list 1 2)")),
+            Ok(vec![1, 2])
+        );
+        assert_eq!(Option::<i64>::from_expression(parse("(do nil)")), Ok(None));
+        assert_eq!(
+            <(i64, String, bool)>::from_expression(parse("(do [1 \"b\" true])")),
+            Ok((1, "b".to_string(), true))
+        );
+        assert_eq!(
+            <(i64, i64)>::from_expression(parse("(do [1])")).map_err(|e| e.kind()),
+            Err(ErrorKind::ImpossibleConversion)
+        );
+        assert_eq!(
+            bool::from_expression(parse("(do 1)")).map_err(|e| e.kind()),
+            Err(ErrorKind::ImpossibleConversion)
+        );
+
+        assert_eq!(
+            (1, "a", vec![true]).into_expression(),
+            parse("(do [1 \"a\" [true]])")
+        );
+        assert_eq!(Some(1.5).into_expression(), Expression::Float(1.5));
+        assert_eq!(None::<i64>.into_expression(), Expression::Nil);
+    }
+
+    #[test]
+    fn register() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        env.register("clamp", |x: i64, lo: i64, hi: i64| -> Result<i64, _> {
+            Ok(x.max(lo).min(hi))
+        })
+        .unwrap();
+        assert_eq!(
+            env.parse_eval("(clamp (+ 10 5) 0 10)"),
+            Ok(Expression::Number(10))
+        );
+        assert_eq!(
+            env.parse_eval("(map (fn [x] (clamp x 0 2)) [-1 1 3])"),
+            env.parse_eval("(list 0 1 2)")
+        );
+
+        // Arity and type errors map onto the usual error kinds
+        let err = env.parse_eval("(clamp 1 2)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TooFewArgs);
+        assert_eq!(err.context.unwrap().function.as_deref(), Some("clamp"));
+        assert_eq!(
+            env.parse_eval("(clamp 1 2 3 4)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooManyArgs)
+        );
+        let err = env.parse_eval("(clamp \"1\" 2 3)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ImpossibleConversion);
+        let context = err.context.unwrap();
+        assert_eq!(context.function.as_deref(), Some("clamp"));
+        assert_eq!(context.value_type, Some("string"));
+
+        env.register("greet", |name: Option<String>| {
+            Ok(alloc::format!(
+                "Hello, {}!",
+                name.as_deref().unwrap_or("world")
+            ))
+        })
+        .unwrap();
+        assert_eq!(
+            env.parse_eval("(greet nil)"),
+            Ok(Expression::String("Hello, world!".to_string()))
+        );
+        env.register("sum", |xs: Vec<f64>| Ok(xs.iter().sum::<f64>()))
+            .unwrap();
+        assert_eq!(env.parse_eval("(sum [1 2.5])"), Ok(Expression::Float(3.5)));
+        env.register("split-at", |n: i64, xs: Vec<Expression>| {
+            let (a, b) = xs.split_at((n as usize).min(xs.len()));
+            Ok((a.to_vec(), b.to_vec()))
+        })
+        .unwrap();
+        assert_eq!(
+            env.parse_eval("(split-at 1 [:a :b :c])"),
+            env.parse_eval("(do [[:a] [:b :c]])")
+        );
+        env.register("answer", || Ok(42)).unwrap();
+        assert_eq!(env.parse_eval("(answer)"), Ok(Expression::Number(42)));
+        env.register("fail", |msg: String| -> Result<(), _> {
+            Err(Error::thrown(Expression::String(msg)))
+        })
+        .unwrap();
+        assert_eq!(
+            env.parse_eval("(fail \"no\")").map_err(|e| e.kind()),
+            Err(ErrorKind::Thrown)
+        );
+    }
+
+    #[test]
+    fn call() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        env.parse_eval(
+            "(do (def threshold 10)
+                 (def events [])
+                 (defn on-event [code kind]
+                   (def events (conj events [code kind]))
+                   (> code threshold)))",
+        )
+        .unwrap();
+
+        assert_eq!(
+            env.call("on-event", (42, "click")),
+            Ok(Expression::Bool(true))
+        );
+        assert_eq!(
+            env.call("on-event", vec![Expression::Number(1), Expression::Nil]),
+            Ok(Expression::Bool(false))
+        );
+        assert_eq!(
+            env.get_global::<Vec<(i64, Option<String>)>>("events"),
+            Ok(vec![(42, Some("click".to_string())), (1, None)])
+        );
+        assert_eq!(env.get_global::<i64>("threshold"), Ok(10));
+        assert_eq!(env.call("+", (1, 2.5)), Ok(Expression::Float(3.5)));
+        assert_eq!(env.call("list", ()), env.parse_eval("(list)"));
+
+        // Args are passed as values, and are not evaluated again
+        assert_eq!(
+            env.call("first", (vec![Expression::Symbol("x".to_string())],)),
+            Ok(Expression::Symbol("x".to_string()))
+        );
+
+        let err = env.call("on-click", ()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DataNotFound);
+        assert_eq!(
+            alloc::format!("{}", err),
+            "Entry was not found. Symbol: `on-click`."
+        );
+        let err = env.call("threshold", (1,)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ExpectedFunction);
+        assert_eq!(
+            alloc::format!("{}", err),
+            "Expected a function. Symbol: `threshold`. Found a value of type `integer`."
+        );
+        assert_eq!(
+            env.call("on-event", (1,)).map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        let err = env.get_global::<bool>("threshold").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ImpossibleConversion);
+        assert_eq!(err.context.unwrap().symbol.as_deref(), Some("threshold"));
+        assert_eq!(
+            env.get_global::<i64>("missing").map_err(|e| e.kind()),
+            Err(ErrorKind::DataNotFound)
+        );
+    }
+}
@@ -0,0 +1,358 @@
+extern crate alloc;
+
+use crate::Environment;
+use crate::Expression;
+use crate::{Error, ErrorKind};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// Binding forms, such as those of `let` and `fn`, accept a pattern wherever
+// they accept a name. A pattern is one of:
+//
+// - a symbol, which binds the whole value;
+// - a vector, such as `[a b & more :as all]`, which binds the items of a list
+//   or vector by position. Missing items bind `nil`, and the items after `&`
+//   are bound as a vector, or `nil` if there are none;
+// - a map, such as `{x :x [y z] :yz :keys [w] :or {w 0} :as m}`, which binds
+//   the values of a map by key. `:keys` and `:strs` bind each symbol to the
+//   value of the keyword or string of the same name, and `:or` gives defaults
+//   for keys which are missing.
+//
+// `:as` binds the whole value. Patterns may be nested, and `nil` destructures
+// as an empty collection.
+
+fn invalid_pattern(pattern: &Expression) -> Error {
+    Error::new(ErrorKind::ExpectedSymbol)
+        .with_message("Expected a symbol, vector or map as a binding pattern.")
+        .with_value(pattern)
+}
+
+// The symbol bound by an `:as` entry.
+fn check_as(name: Option<&Expression>) -> Result<(), Error> {
+    match name {
+        Some(Expression::Symbol(_)) => Ok(()),
+        Some(name) => Err(Error::new(ErrorKind::ExpectedSymbol)
+            .with_message("Expected a symbol after :as.")
+            .with_value(name)),
+        None => {
+            Err(Error::new(ErrorKind::ExpectedSymbol).with_message("Expected a symbol after :as."))
+        }
+    }
+}
+
+// Check that a pattern is well formed, so that malformed patterns are reported
+// before anything is bound.
+pub fn check(pattern: &Expression) -> Result<(), Error> {
+    match pattern {
+        Expression::Symbol(name) if name == "&" => Err(Error::new(ErrorKind::UnexpectedSymbol)
+            .with_message("`&` may only be used in a vector pattern.")
+            .with_symbol(name)),
+        Expression::Symbol(_) => Ok(()),
+        Expression::Vector(items) => {
+            let (items, whole) = split_as(items);
+            if let Some(whole) = whole {
+                check_as(whole.first())?;
+                if whole.len() > 1 {
+                    return Err(Error::new(ErrorKind::UnexpectedArgs)
+                        .with_message("Nothing may follow the name given to :as."));
+                }
+            }
+            let (items, rest) = split_rest(items);
+            if let Some(rest) = rest {
+                match rest {
+                    [rest] => check(rest)?,
+                    _ => {
+                        return Err(Error::new(ErrorKind::UnexpectedSymbol)
+                            .with_message("Exactly one pattern must follow `&`."))
+                    }
+                }
+            }
+            items.iter().try_for_each(check)
+        }
+        Expression::Map(entries) => entries.iter().try_for_each(|(k, v)| match k {
+            Expression::Keyword(k) if k == "as" => check_as(Some(v)),
+            Expression::Keyword(k) if k == "keys" || k == "strs" => match v {
+                Expression::Vector(names) if names.iter().all(Expression::is_symbol) => Ok(()),
+                _ => Err(Error::new(ErrorKind::ExpectedSymbol)
+                    .with_message("Expected a vector of symbols after :keys or :strs.")
+                    .with_value(v)),
+            },
+            Expression::Keyword(k) if k == "or" => match v {
+                Expression::Map(defaults) if defaults.keys().all(Expression::is_symbol) => Ok(()),
+                _ => Err(Error::new(ErrorKind::ExpectedSymbol)
+                    .with_message("Expected a map of symbols to defaults after :or.")
+                    .with_value(v)),
+            },
+            pattern => check(pattern),
+        }),
+        _ => Err(invalid_pattern(pattern)),
+    }
+}
+
+// Split the `:as` entry, if any, from the end of a vector pattern.
+fn split_as(items: &[Expression]) -> (&[Expression], Option<&[Expression]>) {
+    match items
+        .iter()
+        .position(|item| *item == Expression::keyword("as"))
+    {
+        Some(idx) => (&items[..idx], Some(&items[idx + 1..])),
+        None => (items, None),
+    }
+}
+
+// Split the rest pattern, if any, from the positional patterns of a vector
+// pattern.
+fn split_rest(items: &[Expression]) -> (&[Expression], Option<&[Expression]>) {
+    match items
+        .iter()
+        .position(|item| *item == Expression::Symbol("&".into()))
+    {
+        Some(idx) => (&items[..idx], Some(&items[idx + 1..])),
+        None => (items, None),
+    }
+}
+
+// Bind the parts of a value to the names in a pattern, which must already have
+// been checked. Bindings are pushed onto the top stack frame.
+pub fn bind(env: &mut Environment, pattern: &Expression, value: Expression) -> Result<(), Error> {
+    match pattern {
+        Expression::Symbol(name) => env.push_stack(name, value),
+        Expression::Vector(patterns) => bind_vector(env, patterns, value),
+        Expression::Map(patterns) => bind_map(env, patterns, value),
+        _ => Err(invalid_pattern(pattern)),
+    }
+}
+
+fn bind_vector(
+    env: &mut Environment,
+    patterns: &[Expression],
+    value: Expression,
+) -> Result<(), Error> {
+    let mut items = match &value {
+        Expression::List(items) | Expression::Vector(items) => items.clone(),
+        Expression::Nil => Vec::new(),
+        _ => {
+            return Err(Error::new(ErrorKind::TypeMismatch)
+                .with_message("Only a list, vector or nil can match a vector pattern.")
+                .with_value(&value))
+        }
+    };
+    let (patterns, whole) = split_as(patterns);
+    if let Some([Expression::Symbol(name)]) = whole {
+        env.push_stack(name, value.clone())?;
+    }
+    let (patterns, rest) = split_rest(patterns);
+    let rest_items = items.split_off(patterns.len().min(items.len()));
+    let mut items = items.into_iter();
+    for pattern in patterns {
+        bind(env, pattern, items.next().unwrap_or(Expression::Nil))?;
+    }
+    if let Some([rest]) = rest {
+        let rest_value = match rest_items.is_empty() {
+            true => Expression::Nil,
+            false => Expression::Vector(rest_items),
+        };
+        bind(env, rest, rest_value)?;
+    }
+    Ok(())
+}
+
+fn bind_map(
+    env: &mut Environment,
+    patterns: &BTreeMap<Expression, Expression>,
+    value: Expression,
+) -> Result<(), Error> {
+    let map = match &value {
+        Expression::Map(map) => map.clone(),
+        Expression::Nil => BTreeMap::new(),
+        _ => {
+            return Err(Error::new(ErrorKind::TypeMismatch)
+                .with_message("Only a map or nil can match a map pattern.")
+                .with_value(&value))
+        }
+    };
+    let defaults = match patterns.get(&Expression::keyword("or")) {
+        Some(Expression::Map(defaults)) => defaults.clone(),
+        _ => BTreeMap::new(),
+    };
+
+    // Look up a key, falling back to the default for the name it is bound to
+    let lookup = |env: &mut Environment, key: &Expression, name: &Expression| match (
+        map.get(key),
+        defaults.get(name),
+    ) {
+        (Some(value), _) => Ok(value.clone()),
+        (None, Some(default)) => env.eval(default.clone()),
+        (None, None) => Ok(Expression::Nil),
+    };
+
+    for (pattern, key) in patterns {
+        match pattern {
+            Expression::Keyword(k) if k == "as" => bind(env, key, value.clone())?,
+            Expression::Keyword(k) if k == "or" => {}
+            Expression::Keyword(k) if k == "keys" || k == "strs" => {
+                let names: Vec<Expression> = key.clone().try_into()?;
+                for name in names {
+                    let sym: String = name.clone().try_into()?;
+                    let key = match k.as_str() {
+                        "keys" => Expression::Keyword(sym),
+                        _ => Expression::String(sym),
+                    };
+                    let value = lookup(env, &key, &name)?;
+                    bind(env, &name, value)?;
+                }
+            }
+            pattern => {
+                let value = lookup(env, key, pattern)?;
+                bind(env, pattern, value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Environment;
+    use crate::ErrorKind;
+    use crate::Expression;
+
+    #[test]
+    fn vectors() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(let [[a b] [1 2]] (+ a b))"),
+            Ok(Expression::Number(3))
+        );
+        assert_eq!(
+            env.parse_eval("(let [[a b & more :as all] [1 2 3 4]] [a b more all])"),
+            env.parse_eval("(do [1 2 [3 4] [1 2 3 4]])")
+        );
+        assert_eq!(
+            env.parse_eval("(let [[a b & more] '(1)] [a b more])"),
+            env.parse_eval("(do [1 nil nil])")
+        );
+        assert_eq!(
+            env.parse_eval("(let [[[a] [_ b]] [[1 2] [3 4]]] [a b])"),
+            env.parse_eval("(do [1 4])")
+        );
+        assert_eq!(
+            env.parse_eval("(let [[a & [b c]] [1 2 3]] [a b c])"),
+            env.parse_eval("(do [1 2 3])")
+        );
+        assert_eq!(env.parse_eval("(let [[a] nil] a)"), Ok(Expression::Nil));
+    }
+
+    #[test]
+    fn maps() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval("(let [{:keys [x y]} {:x 1 :y 2}] (+ x y))"),
+            Ok(Expression::Number(3))
+        );
+        assert_eq!(
+            env.parse_eval("(let [{a :a [b c] :bc :as m} {:a 1 :bc [2 3]}] [a b c (count m)])"),
+            env.parse_eval("(do [1 2 3 2])")
+        );
+        assert_eq!(
+            env.parse_eval(
+                "(let [{:strs [name] :keys [age] :or {age (+ 1 1)}} {\"name\" \"Al\"}] [name age])"
+            ),
+            env.parse_eval("(do [\"Al\" 2])")
+        );
+        assert_eq!(
+            env.parse_eval("(let [{:keys [a]} nil] a)"),
+            Ok(Expression::Nil)
+        );
+    }
+
+    #[test]
+    fn binding_forms() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        assert_eq!(
+            env.parse_eval(
+                "(do (defn dist [{:keys [x y]} [dx dy]] (+ x y dx dy)) (dist {:x 1 :y 2} [3 4]))"
+            ),
+            Ok(Expression::Number(10))
+        );
+        assert_eq!(
+            env.parse_eval("((fn [a & [b c]] [a b c]) 1 2)"),
+            env.parse_eval("(do [1 2 nil])")
+        );
+        assert_eq!(
+            env.parse_eval("(loop [[x & xs] [1 2 3] total 0] (if x (recur xs (+ total x)) total))"),
+            Ok(Expression::Number(6))
+        );
+        env.parse_eval("(def seen [])").unwrap();
+        env.parse_eval("(doseq [[k v] [[:a 1] [:b 2]]] (def seen (conj seen v k)))")
+            .unwrap();
+        assert_eq!(
+            env.parse_eval("(do seen)"),
+            env.parse_eval("(do [1 :a 2 :b])")
+        );
+
+        // The names args are bound to before destructuring are not visible
+        assert_eq!(
+            env.parse_eval("((fn [[a]] #0) [1])").map_err(|e| e.kind()),
+            Err(ErrorKind::DataNotFound)
+        );
+        env.parse_eval("(defn k [[a]] (fn [] #0))").unwrap();
+        assert_eq!(
+            env.parse_eval("((k [7]))").map_err(|e| e.kind()),
+            Err(ErrorKind::DataNotFound)
+        );
+        assert_eq!(
+            env.parse_eval("((fn [[a] b] [a b]) [1] 2)"),
+            env.parse_eval("(do [1 2])")
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        let err = env.parse_eval("(let [[a b] 5] a)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TypeMismatch);
+        assert_eq!(
+            alloc::format!("{}", err),
+            "Mismatched types. Only a list, vector or nil can match a vector pattern. Found a value of type `integer`. (at line 1, column 1)"
+        );
+        assert_eq!(
+            env.parse_eval("(let [[a {:keys [b]}] [1 [2]]] b)")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+        assert_eq!(
+            env.parse_eval("(let [{:keys [a]} [1]] a)")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::TypeMismatch)
+        );
+        assert_eq!(
+            env.parse_eval("(let [1 2] 3)").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedSymbol)
+        );
+        assert_eq!(
+            env.parse_eval("(let [[a & b c] [1]] a)")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedSymbol)
+        );
+        assert_eq!(
+            env.parse_eval("(let [[a :as] [1]] a)")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedSymbol)
+        );
+        assert_eq!(
+            env.parse_eval("(let [{:keys [:a]} {}] 1)")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedSymbol)
+        );
+        assert_eq!(
+            env.parse_eval("(fn [[a 1]] a)").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedSymbol)
+        );
+    }
+}
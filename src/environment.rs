@@ -1,19 +1,51 @@
 extern crate alloc;
 
-use crate::builtins::{core, operators};
-use crate::expression::FnBody;
-use crate::Error;
+use crate::builtins::{core, exception, map, operators, seq, set, string};
+use crate::destructure;
+use crate::expression::{
+    map_from_items, read, read_program, set_from_items, Builtin, Closure, Lambda,
+};
+use crate::span::Span;
 use crate::Expression;
+use crate::{Arity, Error, ErrorKind, TraceFrame};
+use ::core::any::{type_name, Any};
+use ::core::cell::RefCell;
+use alloc::boxed::Box;
 use alloc::collections::LinkedList;
+use alloc::format;
+use alloc::rc::Rc;
 use alloc::string::{String, ToString};
+use alloc::vec;
 use alloc::vec::Vec;
 
+// A single stack frame. Frames pushed for lambda calls open a new lexical
+// scope: symbol lookups do not continue past them, except into the global
+// frame.
+#[derive(Clone, Default)]
+struct Frame {
+    vars: Vec<(String, Expression)>,
+    scope: bool,
+}
+
 // The environment stores builtin functions and runtime data, in order to
-// evaluate microlisp scripts.
+// evaluate microlisp scripts. It may also hold a context value belonging to the
+// host application, which builtins can reach through `with_context`. Clones of
+// an environment share its context.
 #[derive(Clone)]
 pub struct Environment {
     builtins: Vec<(String, Expression)>,
-    stack: LinkedList<Vec<(String, Expression)>>,
+    stack: LinkedList<Frame>,
+    tail: Option<Expression>,
+    recur: Option<Vec<Expression>>,
+    recur_target: Option<usize>,
+    gensym_count: usize,
+    context: Option<Rc<RefCell<Box<dyn Any>>>>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
@@ -21,40 +53,179 @@ impl Environment {
         let mut env = Environment {
             builtins: Vec::new(),
             stack: LinkedList::new(),
+            tail: None,
+            recur: None,
+            recur_target: None,
+            gensym_count: 0,
+            context: None,
         };
-        env.stack.push_front(Vec::new());
+        env.stack.push_front(Frame::default());
         env
     }
 
     pub fn load_builtin(
         &mut self,
         name: &str,
-        (params, body): (&str, FnBody),
+        (params, body): (&str, impl Into<Builtin>),
     ) -> Result<(), Error> {
         // Must not duplicate symbol
-        if self.builtins.iter().map(|(k, _)| k).any(|k| k == &name) {
-            Err(Error::DuplicateSymbol)
+        if self.builtins.iter().map(|(k, _)| k).any(|k| k == name) {
+            Err(Error::new(ErrorKind::DuplicateSymbol))
         } else {
             self.builtins.push((
                 name.to_string(),
-                Expression::Function(params.to_string(), body),
+                Expression::Function(params.to_string(), body.into()),
             ));
             Ok(())
         }
     }
 
+    // Load a builtin implemented by a closure, which may capture state of its own,
+    // such as a handle to a device or connection. Like any builtin, the closure
+    // pops its args by the names given in `params`.
+    pub fn load_closure(
+        &mut self,
+        name: &str,
+        params: &str,
+        body: impl Fn(&mut Environment) -> Result<Expression, Error> + 'static,
+    ) -> Result<(), Error> {
+        self.load_builtin(name, (params, Rc::new(body) as Rc<Closure>))
+    }
+
+    // Load a builtin implemented by a closure which mutates the state it captures.
+    // Such a builtin cannot be called again while it is already running, as from
+    // a function passed to it.
+    pub fn load_closure_mut(
+        &mut self,
+        name: &str,
+        params: &str,
+        body: impl FnMut(&mut Environment) -> Result<Expression, Error> + 'static,
+    ) -> Result<(), Error> {
+        let body = RefCell::new(body);
+        let function = name.to_string();
+        self.load_closure(name, params, move |env| match body.try_borrow_mut() {
+            Ok(mut body) => body(env),
+            Err(_) => Err(Error::new(ErrorKind::ReentrantCall).with_function(&function)),
+        })
+    }
+
+    // Set the host context, replacing any context set before.
+    pub fn set_context<T: Any>(&mut self, context: T) {
+        self.context = Some(Rc::new(RefCell::new(Box::new(context))));
+    }
+
+    // Remove and return the host context, if it is of the given type and not
+    // shared with a clone of this environment.
+    pub fn take_context<T: Any>(&mut self) -> Option<T> {
+        match Rc::try_unwrap(self.context.take()?) {
+            Ok(context) => match context.into_inner().downcast::<T>() {
+                Ok(context) => Some(*context),
+                Err(context) => {
+                    self.context = Some(Rc::new(RefCell::new(context)));
+                    None
+                }
+            },
+            Err(context) => {
+                self.context = Some(context);
+                None
+            }
+        }
+    }
+
+    // Call `f` with mutable access to both the host context and the environment,
+    // as from a builtin which acts on the host application. Fails if no context
+    // of the given type was set, or if the context is already in use by an
+    // enclosing call.
+    pub fn with_context<T: Any, R>(
+        &mut self,
+        f: impl FnOnce(&mut T, &mut Environment) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        let missing = || {
+            Error::new(ErrorKind::MissingContext).with_message(&format!(
+                "Expected a context of type `{}`.",
+                type_name::<T>()
+            ))
+        };
+        let context = self.context.clone().ok_or_else(missing)?;
+        let mut context = context.try_borrow_mut().map_err(|_| {
+            Error::new(ErrorKind::ReentrantCall).with_message("The host context is already in use.")
+        })?;
+        let context = context.downcast_mut::<T>().ok_or_else(missing)?;
+        f(context, self)
+    }
+
     pub fn load_default_builtins(&mut self) -> Result<(), Error> {
         self.load_builtin("def", core::DEF)?;
+        self.load_builtin("fn", core::FN)?;
+        self.load_builtin("defn", core::DEFN)?;
+        self.load_builtin("defmacro", core::DEFMACRO)?;
+        self.load_builtin("macroexpand-1", core::MACROEXPAND_1)?;
+        self.load_builtin("macroexpand", core::MACROEXPAND)?;
+        self.load_builtin("gensym", core::GENSYM)?;
+        self.load_builtin("quote", core::QUOTE)?;
+        self.load_builtin("quasiquote", core::QUASIQUOTE)?;
         self.load_builtin("let", core::LET)?;
         self.load_builtin("if", core::IF)?;
+        self.load_builtin("when", core::WHEN)?;
+        self.load_builtin("when-not", core::WHEN_NOT)?;
+        self.load_builtin("cond", core::COND)?;
+        self.load_builtin("case", core::CASE)?;
+        self.load_builtin("if-let", core::IF_LET)?;
+        self.load_builtin("when-let", core::WHEN_LET)?;
         self.load_builtin("do", core::DO)?;
+        self.load_builtin("loop", core::LOOP)?;
+        self.load_builtin("recur", core::RECUR)?;
         self.load_builtin("while", core::WHILE)?;
         self.load_builtin("doseq", core::DOSEQ)?;
         self.load_builtin("dotimes", core::DOTIMES)?;
+        self.load_builtin("try", exception::TRY)?;
+        self.load_builtin("throw", exception::THROW)?;
+        self.load_builtin("ex-info", exception::EX_INFO)?;
+        self.load_builtin("ex-message", exception::EX_MESSAGE)?;
+        self.load_builtin("ex-data", exception::EX_DATA)?;
         self.load_builtin("vector", core::VECTOR)?;
+        self.load_builtin("list", core::LIST)?;
+        self.load_builtin("vec", core::VEC)?;
+        self.load_builtin("concat", core::CONCAT)?;
+        self.load_builtin("conj", core::CONJ)?;
         self.load_builtin("nth", core::NTH)?;
         self.load_builtin("peek", core::PEEK)?;
         self.load_builtin("pop", core::POP)?;
+        self.load_builtin("map", seq::MAP)?;
+        self.load_builtin("filter", seq::FILTER)?;
+        self.load_builtin("remove", seq::REMOVE)?;
+        self.load_builtin("reduce", seq::REDUCE)?;
+        self.load_builtin("first", seq::FIRST)?;
+        self.load_builtin("rest", seq::REST)?;
+        self.load_builtin("cons", seq::CONS)?;
+        self.load_builtin("empty?", seq::IS_EMPTY)?;
+        self.load_builtin("take", seq::TAKE)?;
+        self.load_builtin("drop", seq::DROP)?;
+        self.load_builtin("reverse", seq::REVERSE)?;
+        self.load_builtin("range", seq::RANGE)?;
+        self.load_builtin("some", seq::SOME)?;
+        self.load_builtin("every?", seq::IS_EVERY)?;
+        self.load_builtin("into", seq::INTO)?;
+        self.load_builtin("partition", seq::PARTITION)?;
+        self.load_builtin("group-by", seq::GROUP_BY)?;
+        self.load_builtin("frequencies", seq::FREQUENCIES)?;
+        self.load_builtin("get", map::GET)?;
+        self.load_builtin("get-in", map::GET_IN)?;
+        self.load_builtin("assoc", map::ASSOC)?;
+        self.load_builtin("assoc-in", map::ASSOC_IN)?;
+        self.load_builtin("dissoc", map::DISSOC)?;
+        self.load_builtin("update", map::UPDATE)?;
+        self.load_builtin("update-in", map::UPDATE_IN)?;
+        self.load_builtin("keys", map::KEYS)?;
+        self.load_builtin("vals", map::VALS)?;
+        self.load_builtin("contains?", map::CONTAINS)?;
+        self.load_builtin("merge", map::MERGE)?;
+        self.load_builtin("set", set::SET)?;
+        self.load_builtin("disj", set::DISJ)?;
+        self.load_builtin("union", set::UNION)?;
+        self.load_builtin("intersection", set::INTERSECTION)?;
+        self.load_builtin("difference", set::DIFFERENCE)?;
+        self.load_builtin("subset?", set::SUBSET)?;
         self.load_builtin("+", operators::ADD)?;
         self.load_builtin("-", operators::SUB)?;
         self.load_builtin("*", operators::MUL)?;
@@ -72,6 +243,16 @@ impl Environment {
         self.load_builtin("and", operators::AND)?;
         self.load_builtin("or", operators::OR)?;
         self.load_builtin("not", operators::NOT)?;
+        self.load_builtin("str", string::STR)?;
+        self.load_builtin("count", string::COUNT)?;
+        self.load_builtin("subs", string::SUBS)?;
+        self.load_builtin("upper-case", string::UPPER_CASE)?;
+        self.load_builtin("lower-case", string::LOWER_CASE)?;
+        self.load_builtin("trim", string::TRIM)?;
+        self.load_builtin("split", string::SPLIT)?;
+        self.load_builtin("join", string::JOIN)?;
+        self.load_builtin("starts-with?", string::STARTS_WITH)?;
+        self.load_builtin("includes?", string::INCLUDES)?;
         Ok(())
     }
 
@@ -85,7 +266,7 @@ impl Environment {
 
     pub fn define_var(&mut self, name: &str, var: Expression) -> Result<(), Error> {
         // Variable definitions go in the oldest (backmost) stack frame
-        let frame = self.stack.back_mut().ok_or(Error::StackError)?;
+        let frame = &mut self.stack.back_mut().ok_or(ErrorKind::StackError)?.vars;
         if let Some((idx, _)) = frame.iter().enumerate().find(|(_, (k, _))| k == name) {
             frame[idx].1 = var;
         } else {
@@ -99,17 +280,19 @@ impl Environment {
         if let Some(idx) = self
             .stack
             .front()
-            .ok_or(Error::StackError)?
+            .ok_or(ErrorKind::StackError)?
+            .vars
             .iter()
             .enumerate()
             .find_map(|(idx, (k, _))| if k == name { Some(idx) } else { None })
         {
-            self.stack.front_mut().ok_or(Error::StackError)?[idx].1 = var;
+            self.stack.front_mut().ok_or(ErrorKind::StackError)?.vars[idx].1 = var;
             Ok(())
         } else {
             self.stack
                 .front_mut()
-                .ok_or(Error::StackError)?
+                .ok_or(ErrorKind::StackError)?
+                .vars
                 .push((name.to_string(), var));
             Ok(())
         }
@@ -118,150 +301,894 @@ impl Environment {
     // If the top item in the stack matches the given name, pop that item from the
     // stack Only searches top stack frame. Does not recurse into lower stack
     // frames.
-    pub(crate) fn pop_stack_if_named(&mut self, name: &str) -> Result<Expression, Error> {
+    pub fn pop_stack_if_named(&mut self, name: &str) -> Result<Expression, Error> {
         if self
             .stack
             .front()
-            .ok_or(Error::StackError)?
+            .ok_or(ErrorKind::StackError)?
+            .vars
             .last()
-            .ok_or(Error::DataNotFound)?
+            .ok_or(ErrorKind::DataNotFound)?
             .0
             == name
         {
-            Ok(self.stack.front_mut().unwrap().pop().unwrap().1)
+            Ok(self.stack.front_mut().unwrap().vars.pop().unwrap().1)
         } else {
-            Err(Error::DataNotFound)
+            Err(Error::new(ErrorKind::DataNotFound))
         }
     }
 
-    // Search the stack for a symbol. If the symbol exists in multiple stack frames,
-    // this method will return the instance from the newest (frontmost) frame.
+    // The non-global frames visible from the code currently being evaluated, from
+    // newest to oldest. Visibility ends at the frame of the innermost lambda call,
+    // so a function never sees the local bindings of its caller.
+    fn local_frames(&self) -> impl Iterator<Item = &Frame> {
+        let count = match self.stack.iter().position(|frame| frame.scope) {
+            Some(idx) => idx + 1,
+            None => self.stack.len().saturating_sub(1),
+        };
+        self.stack.iter().take(count)
+    }
+
+    // Search the visible local frames for a symbol.
+    fn find_local(&self, name: &str) -> Option<&Expression> {
+        self.local_frames()
+            .flat_map(|frame| frame.vars.iter())
+            .find_map(|(k, v)| if k == name { Some(v) } else { None })
+    }
+
+    // Search the visible stack frames for a symbol, falling back to the global
+    // frame. If the symbol exists in multiple stack frames, this method will return
+    // the instance from the newest (frontmost) frame.
     pub(crate) fn find_data(&self, name: &str) -> Option<&Expression> {
+        self.find_local(name).or_else(|| self.find_global(name))
+    }
+
+    // Search the global frame for a symbol.
+    fn find_global(&self, name: &str) -> Option<&Expression> {
         self.stack
+            .back()?
+            .vars
             .iter()
-            .map(|v| v.iter())
-            .flatten()
             .find_map(|(k, v)| if k == name { Some(v) } else { None })
     }
 
+    // Collect every local binding visible from the current scope, so that a lambda
+    // created here can see them when it is later called. Globals are not captured;
+    // they are looked up when used.
+    pub(crate) fn capture(&self) -> Vec<(String, Expression)> {
+        let mut captured: Vec<(String, Expression)> = Vec::new();
+        for (k, v) in self.local_frames().flat_map(|frame| frame.vars.iter()) {
+            if !captured.iter().any(|(name, _)| name == k) {
+                captured.push((k.clone(), v.clone()));
+            }
+        }
+        captured
+    }
+
     pub fn stack_height(&self) -> usize {
         self.stack.len()
     }
 
     pub fn stack_frame_len(&self) -> usize {
-        self.stack.front().unwrap().len()
+        self.stack.front().unwrap().vars.len()
     }
 
     pub fn eval(&mut self, expr: Expression) -> Result<Expression, Error> {
-        match expr {
-            // Evaluating a list is the most complicated, because a list must be evaluated as a
-            // function form. That is: the first item must be a symbol referring to a function, and
-            // the following items must be suppliable as function args.
-            Expression::List(mut args) => {
-                if args.is_empty() {
-                    Ok(Expression::Nil)
-                } else {
-                    // First expression must be a symbol referencing the name of a function
-                    let name: String = args[0].clone().try_into()?;
-
-                    // Create new stack frame for evaluation of this function
-                    self.stack.push_front(Vec::new());
-
-                    // Load the function params/body from the builtins or from data
-                    let (params, FnBody(body)) = if let Some(Expression::Function(params, body)) =
-                        self.find_builtin(&name)
-                    {
-                        Ok((params, body))
-                    } else if let Some(Expression::Function(params, body)) = self.find_data(&name) {
-                        Ok((params, body))
-                    } else {
-                        Err(Error::ExpectedFunction)
-                    }?;
-                    // TODO: Consider using a Box or Arc to prevent cloning these
-                    let params = params.clone();
-                    let body = body.clone();
-
-                    // Split the parameters into a list of named parameters and positional
-                    // parameters.
-                    let (named, positional) = match params.split_once('&') {
-                        Some((a, b)) => (a, Some(b)),
-                        _ => (params.as_str(), None),
+        let res = self.eval_recur(expr);
+
+        // A `recur` is only valid in tail position of a loop body, which is evaluated
+        // by `eval_loop`. If any other evaluation ends with a `recur` still
+        // pending, then that `recur` was not in tail position.
+        match self.recur.take() {
+            Some(_) => Err(Error::new(ErrorKind::RecurOutsideTail)),
+            None => res,
+        }
+    }
+
+    // Evaluate an expression, leaving any `recur` made in tail position pending for
+    // the caller.
+    fn eval_recur(&mut self, expr: Expression) -> Result<Expression, Error> {
+        // Every frame pushed while evaluating this expression is popped before
+        // returning, whether evaluation succeeds or fails.
+        let base = self.stack.len();
+        let mut location = Location::default();
+        let res = self
+            .eval_frames(expr, base, &mut location)
+            .map_err(|e| location.annotate(e));
+        self.tail = None;
+        self.unwind(base);
+        res
+    }
+
+    // Evaluate the body of a loop, whose bindings have already been pushed to the
+    // top stack frame. Whenever the final form ends in a `recur`, `patterns` are
+    // rebound to the recurred values and the body is evaluated again. Rebinding
+    // overwrites the existing bindings, so the stack does not grow as the loop
+    // iterates.
+    pub(crate) fn eval_loop(
+        &mut self,
+        patterns: &[Expression],
+        mut body: Vec<Expression>,
+    ) -> Result<Expression, Error> {
+        let last = match body.pop() {
+            Some(last) => last,
+            None => return Ok(Expression::Nil),
+        };
+        let outer = self.recur_target.replace(self.stack.len());
+        let res = self.iterate_loop(patterns, &body, &last);
+        self.recur_target = outer;
+        res
+    }
+
+    fn iterate_loop(
+        &mut self,
+        patterns: &[Expression],
+        init: &[Expression],
+        last: &Expression,
+    ) -> Result<Expression, Error> {
+        loop {
+            for expr in init {
+                self.eval(expr.clone())?;
+            }
+            let res = self.eval_recur(last.clone())?;
+            let vals = match self.recur.take() {
+                Some(vals) => vals,
+                None => return Ok(res),
+            };
+            let arity = Arity {
+                min: patterns.len(),
+                max: Some(patterns.len()),
+            };
+            if vals.len() < patterns.len() {
+                return Err(Error::new(ErrorKind::TooFewArgs)
+                    .with_function("recur")
+                    .with_arity(arity, vals.len()));
+            } else if vals.len() > patterns.len() {
+                return Err(Error::new(ErrorKind::TooManyArgs)
+                    .with_function("recur")
+                    .with_arity(arity, vals.len()));
+            }
+            patterns
+                .iter()
+                .zip(vals)
+                .try_for_each(|(pattern, val)| destructure::bind(self, pattern, val))?;
+        }
+    }
+
+    // Request another iteration of the innermost loop, with the given values. The
+    // values are bound once the `recur` form has returned to the loop.
+    pub(crate) fn recur(&mut self, vals: Vec<Expression>) -> Result<Expression, Error> {
+        // There must be an enclosing loop, and it must belong to the current function.
+        // A loop cannot be continued from inside a function called by its body.
+        let target = self.recur_target.ok_or(ErrorKind::RecurOutsideTail)?;
+        if self
+            .stack
+            .iter()
+            .take(self.stack.len().saturating_sub(target))
+            .any(|frame| frame.scope)
+        {
+            return Err(Error::new(ErrorKind::RecurOutsideTail));
+        }
+        self.recur = Some(vals);
+        Ok(Expression::Nil)
+    }
+
+    // Evaluate an expression in tail position. A builtin calls this in place of
+    // `eval` for the form whose value it returns, and must return immediately
+    // afterwards. The form is then evaluated by the enclosing `eval` once the
+    // builtin has returned, so that a chain of calls in tail position runs in
+    // constant stack space.
+    pub fn eval_tail(&mut self, expr: Expression) -> Result<Expression, Error> {
+        self.tail = Some(expr);
+        Ok(Expression::Nil)
+    }
+
+    // Evaluate a sequence of forms, as in the body of a `do`. All forms but the
+    // last are evaluated for their side effects, and the last is evaluated in
+    // tail position.
+    pub fn eval_body(&mut self, mut exprs: Vec<Expression>) -> Result<Expression, Error> {
+        match exprs.pop() {
+            Some(last) => {
+                for expr in exprs {
+                    self.eval(expr)?;
+                }
+                self.eval_tail(last)
+            }
+            None => Ok(Expression::Nil),
+        }
+    }
+
+    fn eval_frames(
+        &mut self,
+        mut expr: Expression,
+        base: usize,
+        location: &mut Location,
+    ) -> Result<Expression, Error> {
+        loop {
+            match expr {
+                // Note where a form was read from, in case evaluating it fails.
+                Expression::Spanned(form, form_span) => {
+                    location.span = Some(form_span);
+                    expr = *form;
+                }
+
+                // Evaluating a list is the most complicated, because a list must be evaluated
+                // as a function form. That is: the first item must refer to a function, and the
+                // following items must be suppliable as function args.
+                Expression::List(mut args) => {
+                    if args.is_empty() {
+                        return Ok(Expression::Nil);
+                    }
+
+                    // The first expression is usually a symbol naming a builtin or a function
+                    // stored as data. Any other expression is evaluated, and must produce a
+                    // function.
+                    let (func, name) = match args.remove(0) {
+                        Expression::Symbol(name) => match self.resolve(&name) {
+                            Some(func) => (func.clone(), Some(name)),
+                            None => {
+                                return Err(
+                                    Error::new(ErrorKind::ExpectedFunction).with_symbol(&name)
+                                )
+                            }
+                        },
+                        expr => (self.eval(expr)?, None),
                     };
-                    let named_count = named.split_whitespace().count();
-                    let offset_func = 0;
-                    let offset_named = offset_func + 1;
-                    let offset_positional = offset_named + named_count;
-
-                    // Args are pushed onto the stack in reverse order. First, push the positional
-                    // args (if any) in reverse order. Then push the named args in reverse order.
-
-                    if let Some(pos_str) = positional {
-                        // Must be exactly 0 or 1 positional names supplied. If 0 names are
-                        // supplied, collect all positional args under the generic name `&`.
-                        // Else, use the name given.
-                        let pos_name_count = pos_str.split_whitespace().count();
-                        if pos_name_count > 1 {
-                            return Err(Error::UnexpectedSymbol);
+
+                    expr = match func {
+                        // Builtins receive their args unevaluated, and decide for themselves how
+                        // (and whether) to evaluate them. If the builtin hands back a form in tail
+                        // position, its frame is kept, as the form may refer to bindings the
+                        // builtin made there.
+                        Expression::Function(params, body) => {
+                            self.stack.push_front(Frame::default());
+                            self.bind_args(params.as_str(), args)
+                                .map_err(|e| in_function(e, name.as_deref()))?;
+                            let res = body.call(self)?;
+                            match self.tail.take() {
+                                Some(tail) => tail,
+                                None => return Ok(res),
+                            }
                         }
 
-                        // Optionally push positional args. Collect the args into a vector, and push
-                        // that vector under the given name, if a name was given.
-                        let mut pos_args = Vec::new();
-                        while args.len() > offset_positional {
-                            pos_args.push(args.pop().unwrap());
+                        // Lambdas receive their args evaluated, in the caller's scope. The body
+                        // is then evaluated in a new scope, holding the lambda's captured
+                        // bindings. Every frame above `base` belongs to the forms this call is
+                        // in tail position of, so they are no longer needed.
+                        Expression::Lambda(lambda) => {
+                            let args: Vec<_> =
+                                args.into_iter().map(|e| self.eval(e)).try_collect()?;
+                            self.unwind(base);
+                            self.stack.push_front(Frame {
+                                vars: lambda.env.clone(),
+                                scope: true,
+                            });
+                            self.bind_lambda_args(&lambda, name.as_deref(), args)?;
+                            // A call in tail position replaces its caller in the trace, just as it
+                            // replaces its caller's frame.
+                            location.call = Some(Call {
+                                span: location.span,
+                                name,
+                                lambda: lambda.clone(),
+                            });
+                            self.eval_lambda_body(&lambda)?
                         }
-                        if pos_args.len() > 0 {
-                            let name = pos_str.split_whitespace().next().unwrap_or("&");
-                            self.push_stack(name, Expression::Vector(pos_args))?;
+
+                        // Macros receive their args unevaluated, and produce a new form which is
+                        // evaluated in place of the macro call.
+                        Expression::Macro(mac) => self.expand_macro(&mac, args)?,
+
+                        // Keywords look themselves up in a map, as in `(:k m)` or `(:k m
+                        // default)`, and maps look up their arg, as in `(m :k)` or `(m :k
+                        // default)`.
+                        func @ (Expression::Keyword(_) | Expression::Map(_)) => {
+                            let args: Vec<_> =
+                                args.into_iter().map(|e| self.eval(e)).try_collect()?;
+                            return lookup(func, args);
                         }
-                    } else if args.len() > offset_positional {
-                        return Err(Error::TooManyArgs);
-                    }
 
-                    // Push all named args onto the stack
-                    named.split_whitespace().rev().try_for_each(|param| {
-                        self.push_stack(param, args.pop().ok_or(Error::TooFewArgs)?)
-                    })?;
+                        func => {
+                            let err = Error::new(ErrorKind::ExpectedFunction).with_value(&func);
+                            return Err(match name {
+                                Some(name) => err.with_symbol(&name),
+                                None => err,
+                            });
+                        }
+                    };
+                }
 
-                    // After pushing all args to the stack, there should be one arg left: the
-                    // function name.
-                    if args.len() != 1 {
-                        return Err(Error::TooFewArgs);
-                    }
+                // Look up referenced data, if symbol. Builtins may also be referenced by name,
+                // so that they can be passed around like any other function.
+                Expression::Symbol(s) => {
+                    return if let Some(data) = self.resolve(&s) {
+                        Ok(data.clone())
+                    } else {
+                        Err(Error::new(ErrorKind::DataNotFound).with_symbol(&s))
+                    };
+                }
+
+                // Vector literals evaluate each of their items, in order, as `vector`
+                // does. Quote a vector to keep its items as written.
+                Expression::Vector(v) => {
+                    return Ok(Expression::Vector(
+                        v.into_iter().map(|e| self.eval(e)).try_collect()?,
+                    ));
+                }
 
-                    // Evaluate the function body
-                    let res = body(self);
+                // Map literals evaluate each of their keys and values. Keys which are
+                // distinct as written may still evaluate to the same value, which is an
+                // error, just as a duplicate key written in the literal is.
+                Expression::Map(m) => {
+                    let mut items = Vec::with_capacity(m.len() * 2);
+                    for (k, v) in m {
+                        items.push(self.eval(k)?);
+                        items.push(self.eval(v)?);
+                    }
+                    return map_from_items(items);
+                }
 
-                    // Pop the new stack frame and return the result
-                    self.stack.pop_front();
-                    res
+                // Set literals evaluate each of their items, which must be distinct once
+                // evaluated.
+                Expression::Set(s) => {
+                    let items = s.into_iter().map(|e| self.eval(e)).try_collect()?;
+                    return set_from_items(items);
                 }
+
+                // All other expressions just evaluate to themselves.
+                _ => return Ok(expr),
             }
+        }
+    }
 
-            // Look up referenced data, if symbol
-            Expression::Symbol(s) => {
-                if let Some(data) = self.find_data(&s) {
-                    Ok(data.clone())
-                } else {
-                    Err(Error::DataNotFound)
+    // Resolve a symbol, whether at the head of a list form or passed as a value.
+    // Local bindings and then globals shadow builtins, so a script may redefine
+    // a builtin's name.
+    pub(crate) fn resolve(&self, name: &str) -> Option<&Expression> {
+        self.find_data(name).or_else(|| self.find_builtin(name))
+    }
+
+    // Call a macro with the given (unevaluated) args, returning its expansion. The
+    // macro body is evaluated in its own scope, like a lambda body. The args are
+    // passed as plain data, without the spans they were read with.
+    fn expand_macro(&mut self, mac: &Lambda, args: Vec<Expression>) -> Result<Expression, Error> {
+        let args = args.into_iter().map(Expression::strip_spans).collect();
+        let height = self.stack.len();
+        self.stack.push_front(Frame {
+            vars: mac.env.clone(),
+            scope: true,
+        });
+        let res = self
+            .call_lambda(mac, mac.name.as_deref(), args)
+            .and_then(|last| self.eval(last));
+        self.unwind(height);
+        res
+    }
+
+    // If `form` is a call to a macro, expand it once and return the expansion.
+    // Otherwise, return `None`.
+    pub(crate) fn macroexpand_1(&mut self, form: &Expression) -> Result<Option<Expression>, Error> {
+        if let Expression::List(items) = form {
+            if let Some(Expression::Symbol(name)) = items.first() {
+                if let Some(Expression::Macro(mac)) = self.resolve(name).cloned() {
+                    return self.expand_macro(&mac, items[1..].to_vec()).map(Some);
                 }
             }
+        }
+        Ok(None)
+    }
+
+    // Generate a symbol which is unique within this environment, for use by macros
+    // that need to bind names without capturing the names used by their callers.
+    pub(crate) fn gensym(&mut self, prefix: &str) -> Expression {
+        self.gensym_count += 1;
+        Expression::Symbol(format!("{}__{}", prefix, self.gensym_count))
+    }
+
+    // Pop stack frames until only `height` frames remain.
+    fn unwind(&mut self, height: usize) {
+        while self.stack.len() > height {
+            self.stack.pop_front();
+        }
+    }
+
+    // Push args onto the top stack frame, according to the given parameter list.
+    // Parameters take the form "a b & rest": named parameters are bound
+    // one-to-one with the args, while any remaining args are collected into a
+    // vector under the name following `&`.
+    fn bind_args(&mut self, params: &str, mut args: Vec<Expression>) -> Result<(), Error> {
+        // Split the parameters into a list of named parameters and positional
+        // parameters.
+        let (named, positional) = match params.split_once('&') {
+            Some((a, b)) => (a, Some(b)),
+            _ => (params, None),
+        };
+        let named_count = named.split_whitespace().count();
+        let arity = Arity {
+            min: named_count,
+            max: positional.map_or(Some(named_count), |_| None),
+        };
+
+        if let Some(pos_str) = positional {
+            // Must be exactly 0 or 1 positional names supplied. If 0 names are
+            // supplied, collect all positional args under the generic name `&`.
+            // Else, use the name given.
+            let pos_name_count = pos_str.split_whitespace().count();
+            if pos_name_count > 1 {
+                return Err(Error::new(ErrorKind::UnexpectedSymbol));
+            }
+
+            // Optionally push positional args. Collect the args into a vector, and push
+            // that vector under the given name, if a name was given.
+            if args.len() > named_count {
+                let pos_args = args.split_off(named_count);
+                let name = pos_str.split_whitespace().next().unwrap_or("&");
+                self.push_stack(name, Expression::Vector(pos_args))?;
+            }
+        } else if args.len() > named_count {
+            return Err(Error::new(ErrorKind::TooManyArgs).with_arity(arity, args.len()));
+        }
 
-            // All other expressions just evaluate to themselves.
-            _ => Ok(expr),
+        // Push all named args onto the stack
+        if args.len() < named_count {
+            return Err(Error::new(ErrorKind::TooFewArgs).with_arity(arity, args.len()));
         }
+        let named: Vec<_> = named.split_whitespace().zip(args).collect();
+        named
+            .into_iter()
+            .rev()
+            .try_for_each(|(param, arg)| self.push_stack(param, arg))
+    }
+
+    // Bind the (already evaluated) args into the top stack frame, then evaluate
+    // each form of the lambda's body except the last. The final form is
+    // returned unevaluated, to be evaluated by the caller in tail position.
+    fn call_lambda(
+        &mut self,
+        lambda: &Lambda,
+        name: Option<&str>,
+        args: Vec<Expression>,
+    ) -> Result<Expression, Error> {
+        self.bind_lambda_args(lambda, name, args)?;
+        self.eval_lambda_body(lambda)
+    }
+
+    // Bind the (already evaluated) args of a lambda into the top stack frame. The
+    // lambda's own name takes precedence over the name it was called by.
+    fn bind_lambda_args(
+        &mut self,
+        lambda: &Lambda,
+        name: Option<&str>,
+        args: Vec<Expression>,
+    ) -> Result<(), Error> {
+        let name = lambda.name.as_deref().or(name);
+        self.bind_args(lambda.params.as_str(), args)
+            .map_err(|e| in_function(e, name))?;
+
+        // Unlike builtins, lambdas always see their rest parameter, bound to nil if no
+        // positional args were given.
+        if let Some(rest) = lambda.rest_param() {
+            let frame = self.stack.front().ok_or(ErrorKind::StackError)?;
+            if !frame.vars.iter().any(|(k, _)| k == rest) {
+                self.push_stack(rest, Expression::Nil)?;
+            }
+        }
+
+        // Destructure the args matched by patterns, now that they are bound. The
+        // generated names are unbound again, so the body never sees them.
+        for (param, pattern) in &lambda.patterns {
+            let vars = &mut self.stack.front_mut().ok_or(ErrorKind::StackError)?.vars;
+            let arg = match vars.iter().position(|(k, _)| k == param) {
+                Some(idx) => vars.remove(idx).1,
+                None => Expression::Nil,
+            };
+            destructure::bind(self, pattern, arg).map_err(|e| in_function(e, name))?;
+        }
+        Ok(())
+    }
+
+    // Evaluate each form of a lambda's body except the last, which is returned
+    // unevaluated.
+    fn eval_lambda_body(&mut self, lambda: &Lambda) -> Result<Expression, Error> {
+        match lambda.body.split_last() {
+            Some((last, init)) => {
+                for expr in init {
+                    self.eval(expr.clone())?;
+                }
+                Ok(last.clone())
+            }
+            None => Ok(Expression::Nil),
+        }
+    }
+
+    // Call a function with args which have already been evaluated. Each arg is
+    // wrapped in a quote form, so that neither lambdas nor builtins evaluate it a
+    // second time.
+    pub(crate) fn apply(
+        &mut self,
+        func: Expression,
+        args: Vec<Expression>,
+    ) -> Result<Expression, Error> {
+        let (params, body) = core::QUOTE;
+        let mut form = vec![func];
+        form.extend(args.into_iter().map(|arg| {
+            Expression::List(vec![
+                Expression::Function(params.to_string(), body.clone().into()),
+                arg,
+            ])
+        }));
+        self.eval(Expression::List(form))
     }
 
     pub fn parse_eval(&mut self, s: &str) -> Result<Expression, Error> {
-        self.eval(s.parse()?)
+        self.eval(read(s)?)
+    }
+
+    // Evaluate each form of a program in order, returning the value of the last
+    // form, or nil if there are no forms.
+    pub fn eval_program(&mut self, forms: Vec<Expression>) -> Result<Expression, Error> {
+        forms
+            .into_iter()
+            .try_fold(Expression::Nil, |_, form| self.eval(form))
+    }
+
+    // Parse and evaluate every form of a program, such as a script file.
+    pub fn eval_str(&mut self, s: &str) -> Result<Expression, Error> {
+        self.eval_program(read_program(s)?)
+    }
+}
+
+// How far evaluation of a form has got, so that an error can be located in the
+// source and in the chain of function calls which led to it.
+#[derive(Default)]
+struct Location {
+    // The innermost form with a known span
+    span: Option<Span>,
+    // The function whose body is being evaluated
+    call: Option<Call>,
+}
+
+struct Call {
+    lambda: Rc<Lambda>,
+    // The name the function was called by, if it has no name of its own
+    name: Option<String>,
+    // The span of the form which called the function
+    span: Option<Span>,
+}
+
+impl Location {
+    fn annotate(&self, err: Error) -> Error {
+        let mut err = err.or_span(self.span);
+        if let Some(call) = &self.call {
+            let function = call.lambda.name.as_ref().or(call.name.as_ref());
+            err.push_trace(TraceFrame {
+                function: function.cloned(),
+                span: call.span,
+            });
+        }
+        err
+    }
+}
+
+// Attribute an error to the named function, if the function has a name.
+fn in_function(err: Error, name: Option<&str>) -> Error {
+    match name {
+        Some(name) => err.with_function(name),
+        None => err,
+    }
+}
+
+// Look up a key in a map, for a keyword or a map called as a function. The
+// first arg is the map or the key respectively, and may be followed by a
+// default. Anything other than a map contains no keys, so produces the default.
+fn lookup(func: Expression, mut args: Vec<Expression>) -> Result<Expression, Error> {
+    let arity = Arity {
+        min: 1,
+        max: Some(2),
+    };
+    let dnf = match args.len() {
+        0 => return Err(Error::new(ErrorKind::TooFewArgs).with_arity(arity, 0)),
+        1 => Expression::Nil,
+        2 => args.remove(1),
+        n => return Err(Error::new(ErrorKind::TooManyArgs).with_arity(arity, n)),
+    };
+    let arg = args.remove(0);
+    let (map, key) = match func {
+        Expression::Map(_) => (func, arg),
+        _ => (arg, func),
+    };
+    match map {
+        Expression::Map(mut m) => Ok(m.remove(&key).unwrap_or(dnf)),
+        _ => Ok(dnf),
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::expression::Closure;
+    use crate::Environment;
+    use crate::Expression;
+    use crate::{Arity, Error, ErrorKind};
+    use ::core::cell::Cell;
+    use alloc::boxed::Box;
+    use alloc::rc::Rc;
+    use alloc::vec::Vec;
+
     #[test]
     fn it_works() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn eval_str() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        let program = "
+            ; A small script
+            (def base 10)
+            (defn scale [x] (* x base))
+            #| Forms are evaluated in order,
+               and the last value is returned |#
+            (def total (scale 4))
+            total
+        ";
+        assert_eq!(env.eval_str(program), Ok(Expression::Number(40)));
+        assert_eq!(env.eval_str("(scale 2) 7"), Ok(Expression::Number(7)));
+        assert_eq!(env.eval_str(""), Ok(Expression::Nil));
+        assert_eq!(
+            env.eval_str("(def x 1) y").map_err(|e| e.kind()),
+            Err(ErrorKind::DataNotFound)
+        );
+        assert_eq!(env.eval_str("x"), Ok(Expression::Number(1)));
+        assert_eq!(
+            env.eval_str("(def z 1").map_err(|e| e.kind()),
+            Err(ErrorKind::UnterminatedList)
+        );
+    }
+
+    #[test]
+    fn error_spans() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        let line_and_column = |err: Error| {
+            let span = err.span().unwrap();
+            (span.start.line, span.start.column)
+        };
+
+        // The innermost form which failed is reported
+        let program = "(def v [1 2])\n(+ 1\n   (nth v 5 (missing)))";
+        let err = env.eval_str(program).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ExpectedFunction);
+        assert_eq!(line_and_column(err), (3, 13));
+
+        // Errors in a function body are located where the function was defined,
+        // even when it was called in tail position
+        let program = "(defn check [x]\n  (if x (+ x :k) 0))\n\n(check 1)";
+        let err = env.eval_str(program).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ImpossibleConversion);
+        assert_eq!(line_and_column(err), (2, 9));
+
+        // Spans are rendered by `Display`
+        let err = env.eval_str("\n  (undefined)").unwrap_err();
+        assert_eq!(
+            alloc::format!("{}", err),
+            "Expected a function. Symbol: `undefined`. (at line 2, column 3)"
+        );
+    }
+
+    #[test]
+    fn closures() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+
+        // Closures may capture state shared with the host
+        let readings = Rc::new(Cell::new(0));
+        let sensor = readings.clone();
+        env.load_closure("read-sensor", "", move |_env: &mut Environment| {
+            sensor.set(sensor.get() + 1);
+            Ok(Expression::Number(sensor.get() * 10))
+        })
+        .unwrap();
+        assert_eq!(
+            env.parse_eval("(+ (read-sensor) (read-sensor))"),
+            Ok(Expression::Number(30))
+        );
+        assert_eq!(readings.get(), 2);
+
+        // Closures pop their args like any other builtin, and may be passed around
+        let scale = 3;
+        env.load_closure("scale", "x", move |env: &mut Environment| {
+            let x = env.pop_stack_if_named("x")?;
+            let x: i64 = env.eval(x)?.try_into()?;
+            Ok(Expression::Number(x * scale))
+        })
+        .unwrap();
+        assert_eq!(
+            env.parse_eval("(map scale [1 2])"),
+            env.parse_eval("(list 3 6)")
+        );
+        assert_eq!(
+            env.parse_eval("(scale)").map_err(|e| e.kind()),
+            Err(ErrorKind::TooFewArgs)
+        );
+        assert_eq!(
+            env.parse_eval("(== scale scale)"),
+            Ok(Expression::Bool(true))
+        );
+
+        // Mutable closures may not be reentered
+        let mut log = Vec::new();
+        env.load_closure_mut("record", "x", move |env: &mut Environment| {
+            let x = env.pop_stack_if_named("x")?;
+            let x = env.eval(x)?;
+            log.push(x);
+            Ok(Expression::Number(log.len() as i64))
+        })
+        .unwrap();
+        assert_eq!(env.parse_eval("(record :a)"), Ok(Expression::Number(1)));
+        assert_eq!(env.parse_eval("(record :b)"), Ok(Expression::Number(2)));
+        let err = env.parse_eval("(record (record :c))").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ReentrantCall);
+        assert_eq!(err.context.unwrap().function.as_deref(), Some("record"));
+
+        // Boxed closures may be loaded directly
+        let body: Box<Closure> = Box::new(|_env: &mut Environment| Ok(Expression::Nil));
+        env.load_builtin("nothing", ("", body)).unwrap();
+        assert_eq!(env.parse_eval("(nothing)"), Ok(Expression::Nil));
+        assert_eq!(
+            env.load_closure("nothing", "", |_env: &mut Environment| Ok(Expression::Nil))
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::DuplicateSymbol)
+        );
+    }
+
+    #[test]
+    fn error_trace() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        let program = "(defn validate [item]
+  (+ item 1))
+(defn process-batch [batch]
+  (validate batch)
+  :done)
+(process-batch [1 2])";
+        let err = env.eval_str(program).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ImpossibleConversion);
+        let trace: Vec<_> = err
+            .trace()
+            .iter()
+            .map(|frame| {
+                let span = frame.span.unwrap();
+                (
+                    frame.function.as_deref(),
+                    span.start.line,
+                    span.start.column,
+                )
+            })
+            .collect();
+        assert_eq!(
+            trace,
+            [(Some("validate"), 4, 3), (Some("process-batch"), 6, 1)]
+        );
+        assert_eq!(
+            alloc::format!("{}", err),
+            "Conversion is not possible. Expected a number. Found a value of type `vector`. (at line 2, column 3)
+    in `validate` called at line 4, column 3
+    in `process-batch` called at line 6, column 1"
+        );
+
+        // Tail calls replace their caller in the trace
+        let program = "(defn inner [] (missing))
+(defn outer [] (inner))
+((fn [] (outer)))";
+        let err = env.eval_str(program).unwrap_err();
+        let names: Vec<_> = err.trace().iter().map(|f| f.function.as_deref()).collect();
+        assert_eq!(names, [Some("inner")]);
+
+        // Anonymous functions are traced too
+        let err = env
+            .eval_str("(let [f (fn [] (missing))] (+ 1 ((fn [] (f)))))")
+            .unwrap_err();
+        let names: Vec<_> = err.trace().iter().map(|f| f.function.as_deref()).collect();
+        assert_eq!(names, [Some("f")]);
+        let err = env.eval_str("(+ 1 ((fn [] (missing))))").unwrap_err();
+        assert_eq!(err.trace().len(), 1);
+        assert_eq!(err.trace()[0].function, None);
+    }
+
+    #[test]
+    fn error_context() {
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        let mut context = |s: &str| *env.parse_eval(s).unwrap_err().context.unwrap();
+
+        assert_eq!(context("(do missing)").symbol.as_deref(), Some("missing"));
+
+        // Arity errors name the function, and how many args it accepts
+        let ctx = context("(do (defn add [a b] (+ a b)) (add 1))");
+        assert_eq!(ctx.function.as_deref(), Some("add"));
+        assert_eq!(
+            ctx.arity,
+            Some((
+                Arity {
+                    min: 2,
+                    max: Some(2)
+                },
+                1
+            ))
+        );
+        let ctx = context("(let [f (fn [x & xs] x)] (f))");
+        assert_eq!(ctx.function.as_deref(), Some("f"));
+        assert_eq!(ctx.arity, Some((Arity { min: 1, max: None }, 0)));
+        let ctx = context("(def x 1 2)");
+        assert_eq!(ctx.function.as_deref(), Some("def"));
+        assert_eq!(
+            ctx.arity,
+            Some((
+                Arity {
+                    min: 2,
+                    max: Some(2)
+                },
+                3
+            ))
+        );
+
+        // Type errors report the type of the offending value
+        let ctx = context("(+ 1 \"a\")");
+        assert_eq!(ctx.value_type, Some("string"));
+        assert_eq!(ctx.message.as_deref(), Some("Expected a number."));
+        assert_eq!(context("(1 2)").value_type, Some("integer"));
+
+        let err = env.parse_eval("(do (defn one [x] x) (one))").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TooFewArgs);
+        assert_eq!(
+            alloc::format!("{}", err),
+            "Not enough args were supplied. Function: `one`. Expected 1 arg, got 0. (at line 1, column 22)"
+        );
+    }
+
+    #[test]
+    fn context() {
+        #[derive(Debug, PartialEq)]
+        struct World {
+            player: (i64, i64),
+        }
+
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        env.load_closure("move-player", "dx dy", |env: &mut Environment| {
+            let dx = env.pop_stack_if_named("dx")?;
+            let dy = env.pop_stack_if_named("dy")?;
+            let dx: i64 = env.eval(dx)?.try_into()?;
+            let dy: i64 = env.eval(dy)?.try_into()?;
+            env.with_context(|world: &mut World, _env| {
+                world.player.0 += dx;
+                world.player.1 += dy;
+                Ok(Expression::Vector(alloc::vec![
+                    Expression::Number(world.player.0),
+                    Expression::Number(world.player.1),
+                ]))
+            })
+        })
+        .unwrap();
+
+        let err = env.parse_eval("(move-player 1 1)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::MissingContext);
+        assert!(alloc::format!("{}", err).contains("World"));
+
+        env.set_context(World { player: (0, 0) });
+        assert_eq!(
+            env.parse_eval("(do (move-player 3 4) (move-player 1 -1))"),
+            env.parse_eval("(do [4 3])")
+        );
+        assert_eq!(env.take_context::<i64>(), None);
+        assert_eq!(env.take_context(), Some(World { player: (4, 3) }));
+        assert_eq!(env.take_context::<World>(), None);
+
+        // The context may not be borrowed twice at once
+        env.set_context(0i64);
+        let res = env.with_context(|_: &mut i64, env| env.with_context(|_: &mut i64, _| Ok(())));
+        assert_eq!(res.map_err(|e| e.kind()), Err(ErrorKind::ReentrantCall));
+        assert_eq!(env.with_context(|n: &mut i64, _| Ok(*n + 1)), Ok(1));
+    }
 }
@@ -1,54 +1,354 @@
+extern crate alloc;
+
+use crate::expression::Expression;
+use crate::span::Span;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::fmt;
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum Error {
-    DataNotFound,
-    DuplicateSymbol,
-    Empty,
-    ExpectedFunction,
-    ExpectedList,
-    ExpectedSymbol,
-    ExpectedVector,
-    ImpossibleConversion,
-    IncompleteTokenization,
-    MathError,
-    MismatchedDelimiter,
-    StackError,
-    TooFewArgs,
-    TooManyArgs,
-    TypeMismatch,
-    UnbalancedBindings,
-    UnexpectedArgs,
-    UnexpectedSymbol,
-    Unimplemented,
-    Uninitialized,
-    UnterminatedList,
+// Defines the kinds of error, along with the name scripts use to catch each
+// kind and the message displayed for it.
+macro_rules! error_kinds {
+    ($($kind:ident => $name:expr, $message:expr,)*) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum ErrorKind {
+            $($kind,)*
+        }
+
+        impl ErrorKind {
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $(ErrorKind::$kind => $name,)*
+                }
+            }
+
+            // The kind of error with the given name, if there is one.
+            pub fn from_name(name: &str) -> Option<ErrorKind> {
+                [$(ErrorKind::$kind),*].into_iter().find(|kind| kind.name() == name)
+            }
+        }
+
+        impl fmt::Display for ErrorKind {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match *self {
+                    $(ErrorKind::$kind => f.write_str($message),)*
+                }
+            }
+        }
+    };
+}
+
+error_kinds! {
+    DataNotFound => "data-not-found", "Entry was not found.",
+    DuplicateKey => "duplicate-key", "Duplicate key.",
+    DuplicateSymbol => "duplicate-symbol", "Duplicate symbol.",
+    Empty => "empty", "Vector/list is empty.",
+    ExpectedFunction => "expected-function", "Expected a function.",
+    ExpectedList => "expected-list", "Expected a list.",
+    ExpectedSymbol => "expected-symbol", "Expected a symbol.",
+    ExpectedVector => "expected-vector", "Expected a vector.",
+    ImpossibleConversion => "impossible-conversion", "Conversion is not possible.",
+    IncompleteTokenization => "incomplete-tokenization", "Incomplete tokenization.",
+    InvalidEscape => "invalid-escape", "Invalid escape sequence in string.",
+    MathError => "math-error", "Underflow, overflow, or divide by zero error.",
+    MismatchedDelimiter => "mismatched-delimiter", "Mismatched delimiter.",
+    MissingContext => "missing-context", "No host context of the requested type was set.",
+    OutOfRange => "out-of-range", "Index is out of range.",
+    ReentrantCall => "reentrant-call", "A builtin was called while it was already running.",
+    RecurOutsideTail => "recur-outside-tail", "Recur is only allowed in tail position of a loop.",
+    StackError => "stack-error", "Error accessing stack data.",
+    Thrown => "thrown", "An exception was thrown.",
+    TooFewArgs => "too-few-args", "Not enough args were supplied.",
+    TooManyArgs => "too-many-args", "Too many args were supplied.",
+    TypeMismatch => "type-mismatch", "Mismatched types.",
+    UnbalancedBindings => "unbalanced-bindings", "Some bindings do not have a value to bind.",
+    UnbalancedEntries => "unbalanced-entries", "Some keys do not have a value.",
+    UnexpectedArgs => "unexpected-args", "The expected arguments were not found.",
+    UnexpectedSymbol => "unexpected-symbol", "Unexpected symbol found.",
+    Unimplemented => "unimplemented", "Logic has not been implemented.",
+    Uninitialized => "uninitialized", "Item has not been initialized.",
+    UnterminatedComment => "unterminated-comment", "Unterminated block comment.",
+    UnterminatedList => "unterminated-list", "Unterminated list.",
+    UnterminatedString => "unterminated-string", "Unterminated string.",
+}
+
+// The number of args a function accepts: at least `min`, and at most `max`
+// unless the function takes a rest parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", plural(max, "arg")),
+            Some(max) => write!(f, "{} to {} args", self.min, max),
+            None => write!(f, "at least {}", plural(self.min, "arg")),
+        }
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        _ => format!("{} {}s", count, noun),
+    }
+}
+
+// Details of what went wrong, and where, beyond the kind of error. Each detail
+// is only present where it is known.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    // The span of the source form being evaluated when the error occurred
+    pub span: Option<Span>,
+    // The function calls the error propagated out of, innermost first. Calls made
+    // in tail position replace their caller, so the caller does not appear.
+    pub trace: Vec<TraceFrame>,
+    // The symbol which could not be resolved
+    pub symbol: Option<String>,
+    // The function being called
+    pub function: Option<String>,
+    // The number of args the function accepts, and the number it was given
+    pub arity: Option<(Arity, usize)>,
+    // The type of the value which could not be used
+    pub value_type: Option<&'static str>,
+    pub message: Option<String>,
+    // The value thrown by a script
+    pub thrown: Option<Expression>,
+}
+
+// A function call which an error propagated out of. Anonymous functions have
+// no name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceFrame {
+    pub function: Option<String>,
+    // The span of the form which called the function
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.function {
+            Some(function) => write!(f, "in `{}`", function)?,
+            None => f.write_str("in anonymous function")?,
+        }
+        match self.span {
+            Some(span) => write!(f, " called at {}", span),
+            None => Ok(()),
+        }
+    }
+}
+
+// An error, along with any context, such as where it occurred. To check or
+// branch on the kind of an error without regard to its context, match on
+// `kind()`, as in `match err.kind() { ErrorKind::TooFewArgs => ... }`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    // Boxed, including the span and trace, so that an error is no larger than two
+    // words and results stay small
+    pub context: Option<Box<ErrorContext>>,
+}
+
+impl Error {
+    pub const fn new(kind: ErrorKind) -> Self {
+        Error {
+            kind,
+            context: None,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    // The span of the source form being evaluated when the error occurred, if
+    // known.
+    pub fn span(&self) -> Option<Span> {
+        self.context.as_ref().and_then(|context| context.span)
+    }
+
+    // The function calls the error propagated out of, innermost first.
+    pub fn trace(&self) -> &[TraceFrame] {
+        match &self.context {
+            Some(context) => &context.trace,
+            None => &[],
+        }
+    }
+
+    // Record the span of the form which caused this error, unless a more precise
+    // span has already been recorded.
+    pub fn or_span(mut self, span: Option<Span>) -> Self {
+        if span.is_some() && self.span().is_none() {
+            self.context_mut().span = span;
+        }
+        self
+    }
+
+    // Record a function call the error propagated out of.
+    pub(crate) fn push_trace(&mut self, frame: TraceFrame) {
+        self.context_mut().trace.push(frame);
+    }
+
+    fn context_mut(&mut self) -> &mut ErrorContext {
+        self.context.get_or_insert_with(Box::default)
+    }
+
+    pub fn with_symbol(mut self, symbol: &str) -> Self {
+        self.context_mut().symbol = Some(symbol.into());
+        self
+    }
+
+    // Record the function being called, unless the error was already attributed
+    // to a function called within it.
+    pub fn with_function(mut self, function: &str) -> Self {
+        let context = self.context_mut();
+        if context.function.is_none() {
+            context.function = Some(function.into());
+        }
+        self
+    }
+
+    pub fn with_arity(mut self, expected: Arity, actual: usize) -> Self {
+        self.context_mut().arity = Some((expected, actual));
+        self
+    }
+
+    // Record the type of the value which caused this error.
+    pub fn with_value(mut self, value: &Expression) -> Self {
+        self.context_mut().value_type = Some(value.type_name());
+        self
+    }
+
+    pub fn with_message(mut self, message: &str) -> Self {
+        self.context_mut().message = Some(message.into());
+        self
+    }
+
+    // An error thrown by a script, carrying the thrown value.
+    pub fn thrown(value: Expression) -> Self {
+        let mut err = Error::new(ErrorKind::Thrown);
+        err.context_mut().thrown = Some(value);
+        err
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error::new(kind)
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(message) = &self.message {
+            write!(f, " {}", message)?;
+        }
+        if let Some(symbol) = &self.symbol {
+            write!(f, " Symbol: `{}`.", symbol)?;
+        }
+        if let Some(function) = &self.function {
+            write!(f, " Function: `{}`.", function)?;
+        }
+        if let Some((expected, actual)) = &self.arity {
+            write!(f, " Expected {}, got {}.", expected, actual)?;
+        }
+        if let Some(value_type) = self.value_type {
+            write!(f, " Found a value of type `{}`.", value_type)?;
+        }
+        if let Some(thrown) = &self.thrown {
+            write!(f, " Value: {}", thrown)?;
+        }
+        Ok(())
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Error::DataNotFound => f.write_str("Entry was not found."),
-            Error::DuplicateSymbol => f.write_str("Duplicate symbol."),
-            Error::Empty => f.write_str("Vector/list is empty."),
-            Error::ExpectedFunction => f.write_str("Expected a function."),
-            Error::ExpectedList => f.write_str("Expected a list."),
-            Error::ExpectedSymbol => f.write_str("Expected a symbol."),
-            Error::ExpectedVector => f.write_str("Expected a vector."),
-            Error::ImpossibleConversion => f.write_str("Conversion is not possible."),
-            Error::IncompleteTokenization => f.write_str("Incomplete tokenization."),
-            Error::MathError => f.write_str("Underflow, overflow, or divide by zero error."),
-            Error::MismatchedDelimiter => f.write_str("Mismatched delimiter."),
-            Error::StackError => f.write_str("Error accessing stack data."),
-            Error::TooFewArgs => f.write_str("Not enough args were supplied."),
-            Error::TooManyArgs => f.write_str("Too many args were supplied."),
-            Error::TypeMismatch => f.write_str("Mismatched types."),
-            Error::UnbalancedBindings => f.write_str("Some bindings do not have a value to bind."),
-            Error::UnexpectedArgs => f.write_str("The expected arguments were not found."),
-            Error::UnexpectedSymbol => f.write_str("Unexpected symbol found."),
-            Error::Unimplemented => f.write_str("Logic has not been implemented."),
-            Error::Uninitialized => f.write_str("Item has not been initialized."),
-            Error::UnterminatedList => f.write_str("Unterminated list."),
+        fmt::Display::fmt(&self.kind, f)?;
+        if let Some(context) = &self.context {
+            fmt::Display::fmt(context, f)?;
         }
+        if let Some(span) = self.span() {
+            write!(f, " (at {})", span)?;
+        }
+        for frame in self.trace() {
+            write!(f, "\n    {}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arity, Error, ErrorKind};
+    use crate::Expression;
+    use crate::Span;
+    use alloc::format;
+    use core::mem::size_of;
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            format!("{}", Error::new(ErrorKind::Empty)),
+            "Vector/list is empty."
+        );
+        assert_eq!(
+            format!("{}", Error::new(ErrorKind::DataNotFound).with_symbol("x")),
+            "Entry was not found. Symbol: `x`."
+        );
+        let err = Error::new(ErrorKind::TooFewArgs)
+            .with_function("f")
+            .with_arity(Arity { min: 2, max: None }, 1);
+        assert_eq!(
+            format!("{}", err),
+            "Not enough args were supplied. Function: `f`. Expected at least 2 args, got 1."
+        );
+        let err = Error::new(ErrorKind::TooManyArgs).with_arity(
+            Arity {
+                min: 0,
+                max: Some(1),
+            },
+            2,
+        );
+        assert_eq!(
+            format!("{}", err),
+            "Too many args were supplied. Expected 0 to 1 args, got 2."
+        );
+        let err = Error::new(ErrorKind::TypeMismatch)
+            .with_message("Expected a string.")
+            .with_value(&Expression::Number(1));
+        assert_eq!(
+            format!("{}", err),
+            "Mismatched types. Expected a string. Found a value of type `integer`."
+        );
+    }
+
+    #[test]
+    fn context() {
+        let err = Error::new(ErrorKind::TooFewArgs)
+            .with_function("inner")
+            .with_function("outer");
+        assert_eq!(err.context.unwrap().function.as_deref(), Some("inner"));
+        let a = Error::new(ErrorKind::DataNotFound).with_symbol("a");
+        let b = Error::new(ErrorKind::DataNotFound).with_symbol("b");
+        assert_ne!(a, b);
+        assert_eq!(a.kind(), b.kind());
+    }
+
+    #[test]
+    fn size() {
+        // Errors are matched by kind, and stay small so that results do too
+        let err = Error::new(ErrorKind::TooFewArgs).or_span(Some(Span::default()));
+        assert!(matches!(err.kind(), ErrorKind::TooFewArgs));
+        assert_eq!(size_of::<Error>(), 2 * size_of::<usize>());
+        assert_eq!(
+            size_of::<Result<Expression, Error>>(),
+            size_of::<Expression>()
+        );
     }
 }
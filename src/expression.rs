@@ -1,25 +1,122 @@
 extern crate alloc;
 
+use crate::span::{LineIndex, Span};
 use crate::Environment;
-use crate::Error;
+use crate::{Error, ErrorKind};
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::rc::Rc;
 use alloc::string::{String, ToString};
+use alloc::vec;
 use alloc::vec::Vec;
+use core::any::Any;
 use core::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
 use core::fmt;
+use core::hash::{Hash, Hasher};
 use core::ops::Neg;
 use core::str::FromStr;
 
 #[derive(Clone)]
 pub struct FnBody(pub fn(&mut Environment) -> Result<Expression, Error>);
 
+// A builtin implemented by a closure, which may capture state of its own.
+pub type Closure = dyn Fn(&mut Environment) -> Result<Expression, Error>;
+
+// The body of a builtin: either a plain function, which can be built in a
+// const, or a closure.
+#[derive(Clone)]
+pub enum Builtin {
+    Fn(FnBody),
+    Closure(Rc<Closure>),
+}
+
+impl Builtin {
+    pub fn call(&self, env: &mut Environment) -> Result<Expression, Error> {
+        match self {
+            Builtin::Fn(FnBody(body)) => body(env),
+            Builtin::Closure(body) => body(env),
+        }
+    }
+
+    // The address of the function or closure, which identifies the builtin.
+    fn addr(&self) -> usize {
+        match self {
+            Builtin::Fn(FnBody(body)) => *body as usize,
+            Builtin::Closure(body) => Rc::as_ptr(body) as *const () as usize,
+        }
+    }
+}
+
+impl From<FnBody> for Builtin {
+    fn from(body: FnBody) -> Self {
+        Builtin::Fn(body)
+    }
+}
+
+impl From<Rc<Closure>> for Builtin {
+    fn from(body: Rc<Closure>) -> Self {
+        Builtin::Closure(body)
+    }
+}
+
+impl From<Box<Closure>> for Builtin {
+    fn from(body: Box<Closure>) -> Self {
+        Builtin::Closure(body.into())
+    }
+}
+
+// A function defined by a script, via `fn`, `defn` or `defmacro`. Parameters
+// use the same
+// "a b & rest" format as builtins, and the body forms are kept unevaluated
+// until the lambda is called. `env` holds the local bindings that were visible
+// where the lambda was defined.
+pub struct Lambda {
+    pub name: Option<String>,
+    pub params: String,
+    // Destructuring patterns, and the params whose args they destructure
+    pub patterns: Vec<(String, Expression)>,
+    pub body: Vec<Expression>,
+    pub env: Vec<(String, Expression)>,
+}
+
+impl Lambda {
+    // The name given to the rest parameter, if the lambda accepts one.
+    pub fn rest_param(&self) -> Option<&str> {
+        self.params
+            .split_once('&')
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+    }
+}
+
 #[derive(Clone)]
 pub enum Expression {
     Bool(bool),
-    Function(String, FnBody),
+    Float(f64),
+    Function(String, Builtin),
+    Keyword(String),
+    Lambda(Rc<Lambda>),
     List(Vec<Expression>),
+    Macro(Rc<Lambda>),
+    Map(BTreeMap<Expression, Expression>),
     Nil,
     Number(i64),
+    Set(BTreeSet<Expression>),
+    // A form read from source text, along with the span of text it was read
+    // from, so that errors can be located. Spans are ignored when comparing or
+    // hashing expressions. The public parsers, `FromStr` and `parse_program`,
+    // never produce spanned forms, but those kept by the evaluator, such as the
+    // body of a `Lambda` or the unevaluated args a builtin pops, may be spanned.
+    // The `is_*` helpers and conversions look through spans; code matching on
+    // such forms directly should call `as_unspanned`, `unspanned` or
+    // `strip_spans` first.
+    Spanned(Box<Expression>, Span),
+    String(String),
     Symbol(String),
+    // An opaque value belonging to the host application, along with the name of
+    // its type. Scripts may store and pass around userdata, but only builtins can
+    // look inside, by downcasting.
+    Userdata(&'static str, Rc<dyn Any>),
     Vector(Vec<Expression>),
 }
 
@@ -27,46 +124,63 @@ impl Neg for Expression {
     type Output = Self;
 
     fn neg(self) -> Self::Output {
-        let res = match self {
+        match self {
             Expression::Number(x) => Expression::Number(-x),
+            Expression::Float(x) => Expression::Float(-x),
             _ => panic!(),
-        };
-        res
+        }
     }
 }
 
 impl Eq for Expression {}
 
+// Compare two floats, treating NaN as equal to itself and greater than any
+// other value, so that floats have a total order.
+fn float_cmp(l: f64, r: f64) -> Ordering {
+    match l.partial_cmp(&r) {
+        Some(order) => order,
+        None => l.is_nan().cmp(&r.is_nan()),
+    }
+}
+
+// Expressions have a total order, so that any expression may be used as a map
+// key. Expressions of the same kind are compared by value (or, for functions,
+// and userdata, by identity), and expressions of different kinds are ordered by
+// kind.
 impl Ord for Expression {
     fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
+            (Expression::Spanned(l, _), r) => l.as_ref().cmp(r),
+            (l, Expression::Spanned(r, _)) => l.cmp(r),
             (Expression::Bool(l), Expression::Bool(r)) => l.cmp(r),
-            (Expression::List(l), Expression::List(r)) => {
-                match l
-                    .iter()
-                    .zip(r.iter())
-                    .map(|(l, r)| l.cmp(r))
-                    .find(|&c| c != Ordering::Equal)
-                {
-                    Some(order) => order,
-                    None => Ordering::Equal,
-                }
+            (Expression::Float(l), Expression::Float(r)) => float_cmp(*l, *r),
+            // Integers and floats are ordered by value. An integer is ordered before a
+            // float of the same value, since the two are not equal.
+            (Expression::Float(l), Expression::Number(r)) => {
+                float_cmp(*l, *r as f64).then(Ordering::Greater)
+            }
+            (Expression::Number(l), Expression::Float(r)) => {
+                float_cmp(*l as f64, *r).then(Ordering::Less)
+            }
+            (Expression::Function(lp, lb), Expression::Function(rp, rb)) => {
+                lp.cmp(rp).then(lb.addr().cmp(&rb.addr()))
             }
+            (Expression::Keyword(l), Expression::Keyword(r)) => l.cmp(r),
+            (Expression::Lambda(l), Expression::Lambda(r))
+            | (Expression::Macro(l), Expression::Macro(r)) => Rc::as_ptr(l).cmp(&Rc::as_ptr(r)),
+            (Expression::List(l), Expression::List(r)) => l.cmp(r),
+            (Expression::Map(l), Expression::Map(r)) => l.cmp(r),
             (Expression::Nil, Expression::Nil) => Ordering::Equal,
             (Expression::Number(l), Expression::Number(r)) => l.cmp(r),
+            (Expression::Set(l), Expression::Set(r)) => l.cmp(r),
+            (Expression::String(l), Expression::String(r)) => l.cmp(r),
             (Expression::Symbol(l), Expression::Symbol(r)) => l.cmp(r),
-            (Expression::Vector(l), Expression::Vector(r)) => {
-                match l
-                    .iter()
-                    .zip(r.iter())
-                    .map(|(l, r)| l.cmp(r))
-                    .find(|&c| c != Ordering::Equal)
-                {
-                    Some(order) => order,
-                    None => Ordering::Equal,
-                }
+            // Userdata is ordered by identity alone, consistent with equality
+            (Expression::Userdata(_, l), Expression::Userdata(_, r)) => {
+                userdata_addr(l).cmp(&userdata_addr(r))
             }
-            _ => panic!(),
+            (Expression::Vector(l), Expression::Vector(r)) => l.cmp(r),
+            _ => self.rank().cmp(&other.rank()),
         }
     }
 }
@@ -74,42 +188,74 @@ impl Ord for Expression {
 impl PartialEq for Expression {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
+            (Expression::Spanned(l, _), r) => l.as_ref() == r,
+            (l, Expression::Spanned(r, _)) => l == r.as_ref(),
             (Expression::Bool(l), Expression::Bool(r)) => l == r,
-            (Expression::List(l), Expression::List(r)) => {
-                (l.len() == r.len())
-                    && (l
-                        .iter()
-                        .zip(r.iter())
-                        .map(|(l, r)| l == r)
-                        .reduce(|acc, x| acc && x)
-                        .unwrap_or(false))
+            // NaN is equal to itself, so that values containing it can be compared
+            (Expression::Float(l), Expression::Float(r)) => l == r || (l.is_nan() && r.is_nan()),
+            (Expression::Function(lp, lb), Expression::Function(rp, rb)) => {
+                lp == rp && lb.addr() == rb.addr()
             }
+            (Expression::Keyword(l), Expression::Keyword(r)) => l == r,
+            (Expression::Lambda(l), Expression::Lambda(r)) => Rc::ptr_eq(l, r),
+            (Expression::Macro(l), Expression::Macro(r)) => Rc::ptr_eq(l, r),
+            (Expression::List(l), Expression::List(r)) => l == r,
+            (Expression::Map(l), Expression::Map(r)) => l == r,
             (Expression::Nil, Expression::Nil) => true,
             (Expression::Number(l), Expression::Number(r)) => l == r,
+            (Expression::Set(l), Expression::Set(r)) => l == r,
+            (Expression::String(l), Expression::String(r)) => l == r,
             (Expression::Symbol(l), Expression::Symbol(r)) => l == r,
-            (Expression::Vector(l), Expression::Vector(r)) => {
-                (l.len() == r.len())
-                    && (l
-                        .iter()
-                        .zip(r.iter())
-                        .map(|(l, r)| l == r)
-                        .reduce(|acc, x| acc && x)
-                        .unwrap_or(false))
+            (Expression::Userdata(_, l), Expression::Userdata(_, r)) => {
+                userdata_addr(l) == userdata_addr(r)
             }
+            (Expression::Vector(l), Expression::Vector(r)) => l == r,
             _ => false,
         }
     }
 }
 
+// The address of a userdata value, which identifies it.
+fn userdata_addr(value: &Rc<dyn Any>) -> usize {
+    Rc::as_ptr(value) as *const () as usize
+}
+
+// Hashing is consistent with equality: equal floats (including zeros of either
+// sign, and NaNs) hash alike, and functions and userdata hash by identity.
+impl Hash for Expression {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let rank = self.rank();
+        match self {
+            Expression::Spanned(expr, _) => expr.hash(state),
+            Expression::Bool(b) => (rank, b).hash(state),
+            Expression::Float(x) => {
+                let bits = if x.is_nan() {
+                    f64::NAN.to_bits()
+                } else if *x == 0.0 {
+                    0
+                } else {
+                    x.to_bits()
+                };
+                (rank, bits).hash(state)
+            }
+            Expression::Function(params, body) => (rank, params, body.addr()).hash(state),
+            Expression::Lambda(l) | Expression::Macro(l) => (rank, Rc::as_ptr(l)).hash(state),
+            Expression::List(v) | Expression::Vector(v) => (rank, v).hash(state),
+            Expression::Map(m) => (rank, m).hash(state),
+            Expression::Nil => rank.hash(state),
+            Expression::Number(x) => (rank, x).hash(state),
+            Expression::Set(s) => (rank, s).hash(state),
+            Expression::Keyword(s) | Expression::String(s) | Expression::Symbol(s) => {
+                (rank, s).hash(state)
+            }
+            Expression::Userdata(_, value) => (rank, userdata_addr(value)).hash(state),
+        }
+    }
+}
+
 impl PartialOrd for Expression {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match (self, other) {
-            (Expression::Bool(_), Expression::Bool(_))
-            | (Expression::Nil, Expression::Nil)
-            | (Expression::Number(_), Expression::Number(_))
-            | (Expression::Symbol(_), Expression::Symbol(_)) => Some(self.cmp(other)),
-            _ => None,
-        }
+        Some(self.cmp(other))
     }
 }
 
@@ -117,9 +263,21 @@ impl TryInto<bool> for Expression {
     type Error = Error;
 
     fn try_into(self) -> Result<bool, Self::Error> {
-        match self {
+        match self.unspanned() {
             Expression::Bool(x) => Ok(x),
-            _ => Err(Error::ImpossibleConversion),
+            expr => Err(Error::new(ErrorKind::ImpossibleConversion).with_value(&expr)),
+        }
+    }
+}
+
+impl TryInto<f64> for Expression {
+    type Error = Error;
+
+    fn try_into(self) -> Result<f64, Self::Error> {
+        match self.unspanned() {
+            Expression::Float(x) => Ok(x),
+            Expression::Number(x) => Ok(x as f64),
+            expr => Err(Error::new(ErrorKind::ImpossibleConversion).with_value(&expr)),
         }
     }
 }
@@ -128,9 +286,9 @@ impl TryInto<(String, FnBody)> for Expression {
     type Error = Error;
 
     fn try_into(self) -> Result<(String, FnBody), Self::Error> {
-        match self {
-            Expression::Function(a, b) => Ok((a, b)),
-            _ => Err(Error::ImpossibleConversion),
+        match self.unspanned() {
+            Expression::Function(a, Builtin::Fn(b)) => Ok((a, b)),
+            expr => Err(Error::new(ErrorKind::ImpossibleConversion).with_value(&expr)),
         }
     }
 }
@@ -139,9 +297,10 @@ impl TryInto<String> for Expression {
     type Error = Error;
 
     fn try_into(self) -> Result<String, Self::Error> {
-        match self {
+        match self.unspanned() {
+            Expression::String(x) => Ok(x),
             Expression::Symbol(x) => Ok(x),
-            _ => Err(Error::ImpossibleConversion),
+            expr => Err(Error::new(ErrorKind::ImpossibleConversion).with_value(&expr)),
         }
     }
 }
@@ -150,10 +309,32 @@ impl TryInto<Vec<Expression>> for Expression {
     type Error = Error;
 
     fn try_into(self) -> Result<Vec<Expression>, Self::Error> {
-        match self {
+        match self.unspanned() {
             Expression::List(x) => Ok(x),
             Expression::Vector(x) => Ok(x),
-            _ => Err(Error::ImpossibleConversion),
+            expr => Err(Error::new(ErrorKind::ImpossibleConversion).with_value(&expr)),
+        }
+    }
+}
+
+impl TryInto<BTreeMap<Expression, Expression>> for Expression {
+    type Error = Error;
+
+    fn try_into(self) -> Result<BTreeMap<Expression, Expression>, Self::Error> {
+        match self.unspanned() {
+            Expression::Map(x) => Ok(x),
+            expr => Err(Error::new(ErrorKind::ImpossibleConversion).with_value(&expr)),
+        }
+    }
+}
+
+impl TryInto<BTreeSet<Expression>> for Expression {
+    type Error = Error;
+
+    fn try_into(self) -> Result<BTreeSet<Expression>, Self::Error> {
+        match self.unspanned() {
+            Expression::Set(x) => Ok(x),
+            expr => Err(Error::new(ErrorKind::ImpossibleConversion).with_value(&expr)),
         }
     }
 }
@@ -162,40 +343,236 @@ impl TryInto<i64> for Expression {
     type Error = Error;
 
     fn try_into(self) -> Result<i64, Self::Error> {
-        match self {
+        match self.unspanned() {
             Expression::Number(x) => Ok(x),
-            _ => Err(Error::ImpossibleConversion),
+            expr => Err(Error::new(ErrorKind::ImpossibleConversion).with_value(&expr)),
         }
     }
 }
 
 impl Expression {
+    // The position of each kind of expression in the order used by `Ord`.
+    // Integers and floats share a rank, so that they are ordered by value.
+    fn rank(&self) -> u8 {
+        match self {
+            Expression::Nil => 0,
+            Expression::Bool(_) => 1,
+            Expression::Float(_) | Expression::Number(_) => 2,
+            Expression::String(_) => 3,
+            Expression::Keyword(_) => 4,
+            Expression::Symbol(_) => 5,
+            Expression::List(_) => 6,
+            Expression::Vector(_) => 7,
+            Expression::Map(_) => 8,
+            Expression::Set(_) => 9,
+            Expression::Function(_, _) => 10,
+            Expression::Lambda(_) => 11,
+            Expression::Macro(_) => 12,
+            Expression::Userdata(_, _) => 13,
+            Expression::Spanned(expr, _) => expr.rank(),
+        }
+    }
+
+    // A keyword with the given name, which excludes the leading `:`.
+    pub fn keyword(name: &str) -> Expression {
+        Expression::Keyword(name.to_string())
+    }
+
+    // The span of source text this expression was read from, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expression::Spanned(_, span) => Some(*span),
+            _ => None,
+        }
+    }
+
+    // Remove this expression's span, if it has one.
+    pub fn unspanned(self) -> Expression {
+        match self {
+            Expression::Spanned(expr, _) => *expr,
+            expr => expr,
+        }
+    }
+
+    // This expression without its span, if it has one, by reference.
+    pub fn as_unspanned(&self) -> &Expression {
+        match self {
+            Expression::Spanned(expr, _) => expr.as_unspanned(),
+            expr => expr,
+        }
+    }
+
+    // Remove the spans from this expression and every expression within it, so
+    // that it can be treated as plain data.
+    pub fn strip_spans(self) -> Expression {
+        match self {
+            Expression::List(v) => {
+                Expression::List(v.into_iter().map(Expression::strip_spans).collect())
+            }
+            Expression::Map(m) => Expression::Map(
+                m.into_iter()
+                    .map(|(k, v)| (k.strip_spans(), v.strip_spans()))
+                    .collect(),
+            ),
+            Expression::Set(s) => {
+                Expression::Set(s.into_iter().map(Expression::strip_spans).collect())
+            }
+            Expression::Spanned(expr, _) => expr.strip_spans(),
+            Expression::Vector(v) => {
+                Expression::Vector(v.into_iter().map(Expression::strip_spans).collect())
+            }
+            expr => expr,
+        }
+    }
+
+    // The name of this expression's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Expression::Bool(_) => "bool",
+            Expression::Float(_) => "float",
+            Expression::Function(_, _) => "builtin",
+            Expression::Keyword(_) => "keyword",
+            Expression::Lambda(_) => "function",
+            Expression::List(_) => "list",
+            Expression::Macro(_) => "macro",
+            Expression::Map(_) => "map",
+            Expression::Nil => "nil",
+            Expression::Number(_) => "integer",
+            Expression::Set(_) => "set",
+            Expression::Spanned(expr, _) => expr.type_name(),
+            Expression::String(_) => "string",
+            Expression::Symbol(_) => "symbol",
+            Expression::Userdata(name, _) => name,
+            Expression::Vector(_) => "vector",
+        }
+    }
+
+    // Wrap a host value as userdata, with the given type name.
+    pub fn userdata<T: Any>(name: &'static str, value: T) -> Expression {
+        Expression::Userdata(name, Rc::new(value))
+    }
+
+    // Borrow the host value held by userdata, if it is of the given type.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        match self {
+            Expression::Spanned(expr, _) => expr.downcast_ref(),
+            Expression::Userdata(_, value) => value.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    // Get a shared handle to the host value held by userdata, if it is of the
+    // given type.
+    pub fn downcast<T: Any>(&self) -> Option<Rc<T>> {
+        match self {
+            Expression::Spanned(expr, _) => expr.downcast(),
+            Expression::Userdata(_, value) => value.clone().downcast().ok(),
+            _ => None,
+        }
+    }
+
+    // Only `nil` and `false` are logically false. All other values, including the
+    // number `0`, are considered true.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(
+            self.as_unspanned(),
+            Expression::Nil | Expression::Bool(false)
+        )
+    }
+
     pub fn is_bool(&self) -> bool {
-        matches!(self, Expression::Bool(_))
+        matches!(self.as_unspanned(), Expression::Bool(_))
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self.as_unspanned(), Expression::Float(_))
     }
 
     pub fn is_function(&self) -> bool {
-        matches!(self, Expression::Function(_, _))
+        matches!(
+            self.as_unspanned(),
+            Expression::Function(_, _) | Expression::Lambda(_)
+        )
+    }
+
+    pub fn is_keyword(&self) -> bool {
+        matches!(self.as_unspanned(), Expression::Keyword(_))
     }
 
     pub fn is_list(&self) -> bool {
-        matches!(self, Expression::List(_))
+        matches!(self.as_unspanned(), Expression::List(_))
+    }
+
+    pub fn is_macro(&self) -> bool {
+        matches!(self.as_unspanned(), Expression::Macro(_))
+    }
+
+    pub fn is_map(&self) -> bool {
+        matches!(self.as_unspanned(), Expression::Map(_))
     }
 
     pub fn is_nil(&self) -> bool {
-        matches!(self, Expression::Nil)
+        matches!(self.as_unspanned(), Expression::Nil)
     }
 
     pub fn is_number(&self) -> bool {
-        matches!(self, Expression::Number(_))
+        matches!(self.as_unspanned(), Expression::Number(_))
+    }
+
+    pub fn is_set(&self) -> bool {
+        matches!(self.as_unspanned(), Expression::Set(_))
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self.as_unspanned(), Expression::String(_))
     }
 
     pub fn is_symbol(&self) -> bool {
-        matches!(self, Expression::Symbol(_))
+        matches!(self.as_unspanned(), Expression::Symbol(_))
+    }
+
+    pub fn is_userdata(&self) -> bool {
+        matches!(self.as_unspanned(), Expression::Userdata(_, _))
     }
 
     pub fn is_vector(&self) -> bool {
-        matches!(self, Expression::Vector(_))
+        matches!(self.as_unspanned(), Expression::Vector(_))
+    }
+}
+
+// Write a string as a double-quoted literal, escaping any characters which the
+// reader would not otherwise read back unchanged.
+fn write_escaped(s: &str, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\r' => f.write_str("\\r")?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+            c => fmt::Write::write_char(f, c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+// Write a float so that the reader will read it back as a float: finite values
+// always include a decimal point, and the non-finite values use `##` syntax.
+fn write_float(x: f64, f: &mut fmt::Formatter) -> fmt::Result {
+    if x.is_nan() {
+        f.write_str("##NaN")
+    } else if x.is_infinite() {
+        f.write_str(if x > 0.0 { "##Inf" } else { "##-Inf" })
+    } else {
+        let s = format!("{}", x);
+        f.write_str(&s)?;
+        if s.contains('.') {
+            Ok(())
+        } else {
+            f.write_str(".0")
+        }
     }
 }
 
@@ -203,6 +580,7 @@ impl fmt::Debug for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
             Expression::Bool(ref b) => fmt::Display::fmt(b, f),
+            Expression::Float(x) => write_float(x, f),
             Expression::Function(ref params, ref _closure) => {
                 f.write_str("(fn [")?;
                 for e in params.split_whitespace() {
@@ -210,6 +588,20 @@ impl fmt::Debug for Expression {
                 }
                 f.write_str("] (...))")
             }
+            Expression::Keyword(ref k) => write!(f, ":{}", k),
+            Expression::Lambda(ref lambda) | Expression::Macro(ref lambda) => {
+                f.write_str(match self {
+                    Expression::Macro(_) => "(macro [",
+                    _ => "(fn [",
+                })?;
+                f.write_str(lambda.params.as_str())?;
+                f.write_str("]")?;
+                lambda.body.iter().try_for_each(|e| {
+                    f.write_str(" ")?;
+                    fmt::Display::fmt(e, f)
+                })?;
+                f.write_str(")")
+            }
             Expression::List(ref l) => {
                 f.write_str("(")?;
                 l.iter().try_for_each(|e| {
@@ -218,8 +610,26 @@ impl fmt::Debug for Expression {
                 })?;
                 f.write_str(" )")
             }
+            Expression::Map(ref m) => {
+                f.write_str("{")?;
+                m.iter().try_for_each(|(k, v)| {
+                    f.write_str(" ")?;
+                    fmt::Display::fmt(k, f)?;
+                    f.write_str(" ")?;
+                    fmt::Display::fmt(v, f)
+                })?;
+                f.write_str(" }")
+            }
             Expression::Nil => f.write_str("nil"),
             Expression::Number(ref n) => fmt::Display::fmt(n, f),
+            Expression::Set(ref s) => {
+                f.write_str("#{")?;
+                s.iter().try_for_each(|e| {
+                    f.write_str(" ")?;
+                    fmt::Display::fmt(e, f)
+                })?;
+                f.write_str(" }")
+            }
             Expression::Vector(ref v) => {
                 f.write_str("[")?;
                 v.iter().try_for_each(|e| {
@@ -228,31 +638,46 @@ impl fmt::Debug for Expression {
                 })?;
                 f.write_str(" ]")
             }
+            Expression::Spanned(ref expr, _) => fmt::Display::fmt(expr, f),
+            Expression::String(ref s) => write_escaped(s, f),
             Expression::Symbol(ref s) => fmt::Display::fmt(s, f),
+            Expression::Userdata(name, _) => write!(f, "#<{}>", name),
+        }
+    }
+}
+
+// Parse a single list, as `FromStr` does, but keep the spans of the forms read,
+// for use by the evaluator.
+pub(crate) fn read(s: &str) -> Result<Expression, Error> {
+    // To parse a string, it must be a list of expressions (i.e. starts with '(')
+    // We strip the list opener, so that tokenization will complete when it hits the
+    // final closing delimiter: ')'
+    if let Some(rem) = s.strip_prefix('(') {
+        let index = LineIndex::new(s);
+        let (rem, items) =
+            read_items(&index, rem, ')').map_err(|e| e.or_span(Some(index.span(s, rem))))?;
+        if skip_comments(rem)?.is_empty() {
+            Ok(Expression::Spanned(
+                Box::new(Expression::List(items)),
+                index.span(s, rem),
+            ))
+        } else {
+            // We require the entire string form a list. If there is some remaining data
+            // after tokenization, then that data represents expressions outside of the
+            // top-level list, which we do not allow.
+            Err(Error::new(ErrorKind::IncompleteTokenization))
         }
+    } else {
+        Err(Error::new(ErrorKind::ExpectedList))
     }
 }
 
+// Parsing produces plain data: the spans the reader records are removed.
 impl FromStr for Expression {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Expression, Self::Err> {
-        // To parse a string, it must be a list of expressions (i.e. starts with '(')
-        // We strip the list opener, so that tokenization will complete when it hits the
-        // final closing delimiter: ')'
-        if let Some(rem) = s.strip_prefix('(') {
-            let (rem, expr) = tokenize(rem)?;
-            if rem.is_empty() {
-                Ok(expr)
-            } else {
-                // We require the entire string form a list. If there is some remaining data
-                // after tokenization, then that data represents expressions outside of the
-                // top-level list, which we do not allow.
-                Err(Error::IncompleteTokenization)
-            }
-        } else {
-            Err(Error::ExpectedList)
-        }
+        read(s).map(Expression::strip_spans)
     }
 }
 
@@ -260,6 +685,7 @@ impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Expression::Bool(ref b) => fmt::Display::fmt(b, f),
+            Expression::Float(x) => write_float(x, f),
             Expression::Function(ref params, ref _closure) => {
                 f.write_str("(fn [")?;
                 for e in params.split_whitespace() {
@@ -267,6 +693,20 @@ impl fmt::Display for Expression {
                 }
                 f.write_str("] (...))")
             }
+            Expression::Keyword(ref k) => write!(f, ":{}", k),
+            Expression::Lambda(ref lambda) | Expression::Macro(ref lambda) => {
+                f.write_str(match self {
+                    Expression::Macro(_) => "(macro [",
+                    _ => "(fn [",
+                })?;
+                f.write_str(lambda.params.as_str())?;
+                f.write_str("]")?;
+                lambda.body.iter().try_for_each(|e| {
+                    f.write_str(" ")?;
+                    fmt::Display::fmt(e, f)
+                })?;
+                f.write_str(")")
+            }
             Expression::List(ref l) => {
                 f.write_str("(")?;
                 l.iter().try_for_each(|e| {
@@ -275,8 +715,26 @@ impl fmt::Display for Expression {
                 })?;
                 f.write_str(" )")
             }
+            Expression::Map(ref m) => {
+                f.write_str("{")?;
+                m.iter().try_for_each(|(k, v)| {
+                    f.write_str(" ")?;
+                    fmt::Display::fmt(k, f)?;
+                    f.write_str(" ")?;
+                    fmt::Display::fmt(v, f)
+                })?;
+                f.write_str(" }")
+            }
             Expression::Nil => f.write_str("nil"),
             Expression::Number(ref n) => fmt::Display::fmt(n, f),
+            Expression::Set(ref s) => {
+                f.write_str("#{")?;
+                s.iter().try_for_each(|e| {
+                    f.write_str(" ")?;
+                    fmt::Display::fmt(e, f)
+                })?;
+                f.write_str(" }")
+            }
             Expression::Vector(ref v) => {
                 f.write_str("[")?;
                 v.iter().try_for_each(|e| {
@@ -285,12 +743,207 @@ impl fmt::Display for Expression {
                 })?;
                 f.write_str(" ]")
             }
+            Expression::Spanned(ref expr, _) => fmt::Display::fmt(expr, f),
+            Expression::String(ref s) => write_escaped(s, f),
             Expression::Symbol(ref s) => fmt::Display::fmt(s, f),
+            Expression::Userdata(name, _) => write!(f, "#<{}>", name),
         }
     }
 }
 
-fn tokenize(s: &str) -> Result<(&str, Expression), Error> {
+// The `#_` prefix, which discards the form that follows it, rather than
+// expanding to another form.
+const DISCARD: &str = "#_";
+
+// Reader shorthands, and the name of the form each one expands to. For example,
+// `'x` is read as `(quote x)`. Longer prefixes are listed first, so that `~@`
+// is not mistaken for `~`.
+const READER_PREFIXES: [(&str, &str); 5] = [
+    (DISCARD, DISCARD),
+    ("~@", "unquote-splicing"),
+    ("'", "quote"),
+    ("`", "quasiquote"),
+    ("~", "unquote"),
+];
+
+// Split any reader prefixes from the front of a token, returning the names of
+// the forms they expand to, outermost first.
+fn strip_prefixes(mut tok: &str) -> (Vec<&'static str>, &str) {
+    let mut prefixes = Vec::new();
+    while let Some((rem, name)) = READER_PREFIXES
+        .iter()
+        .find_map(|(p, name)| tok.strip_prefix(p).map(|rem| (rem, *name)))
+    {
+        prefixes.push(name);
+        tok = rem;
+    }
+    (prefixes, tok)
+}
+
+// Wrap an expression in the forms named by its reader prefixes, innermost
+// first. If a `#_` prefix is reached, the expression is discarded, and any
+// prefixes outside the `#_` are left to apply to the next form.
+fn apply_prefixes(prefixes: &mut Vec<&'static str>, mut expr: Expression) -> Option<Expression> {
+    while let Some(name) = prefixes.pop() {
+        if name == DISCARD {
+            return None;
+        }
+        expr = Expression::List(vec![Expression::Symbol(name.to_string()), expr]);
+    }
+    Some(expr)
+}
+
+// Skip any whitespace, line comments (`; ...`) and block comments (`#| ...
+// |#`) at the start of the input.
+fn skip_comments(mut s: &str) -> Result<&str, Error> {
+    loop {
+        s = s.trim_start();
+        if let Some(comment) = s.strip_prefix(';') {
+            s = comment.split_once('\n').map_or("", |(_, rem)| rem);
+        } else if let Some(comment) = s.strip_prefix("#|") {
+            s = comment
+                .split_once("|#")
+                .map(|(_, rem)| rem)
+                .ok_or(ErrorKind::UnterminatedComment)?;
+        } else {
+            return Ok(s);
+        }
+    }
+}
+
+// Read the body of a string literal, up to and including the closing quote. The
+// opening quote has already been consumed.
+fn read_string(s: &str) -> Result<(&str, Expression), Error> {
+    let mut string = String::new();
+    let mut chars = s.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => return Ok((&s[idx + 1..], Expression::String(string))),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => string.push('\n'),
+                Some((_, 't')) => string.push('\t'),
+                Some((_, 'r')) => string.push('\r'),
+                Some((_, '"')) => string.push('"'),
+                Some((_, '\\')) => string.push('\\'),
+                Some((start, 'u')) => {
+                    // Unicode escapes take the form `\u{1F600}`
+                    let rem = s[start + 1..]
+                        .strip_prefix('{')
+                        .ok_or(ErrorKind::InvalidEscape)?;
+                    let (hex, _) = rem.split_once('}').ok_or(ErrorKind::InvalidEscape)?;
+                    let c = u32::from_str_radix(hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or(ErrorKind::InvalidEscape)?;
+                    string.push(c);
+                    // Skip past the braces and hex digits
+                    chars.nth(hex.len() + 1);
+                }
+                Some(_) => return Err(Error::new(ErrorKind::InvalidEscape)),
+                None => return Err(Error::new(ErrorKind::UnterminatedString)),
+            },
+            c => string.push(c),
+        }
+    }
+    Err(Error::new(ErrorKind::UnterminatedString))
+}
+
+// Parse a float literal, such as `1.5`, `-2e3`, `##Inf`, `##-Inf` or `##NaN`.
+// Tokens must start with a digit (after an optional sign), so that symbols like
+// `inf` or `nan` are not read as floats.
+fn parse_float(tok: &str) -> Option<f64> {
+    match tok {
+        "##Inf" => return Some(f64::INFINITY),
+        "##-Inf" => return Some(f64::NEG_INFINITY),
+        "##NaN" => return Some(f64::NAN),
+        _ => {}
+    }
+    let digits = tok.strip_prefix(['-', '+']).unwrap_or(tok);
+    let digits = digits.strip_prefix('.').unwrap_or(digits);
+    if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        tok.parse::<f64>().ok()
+    } else {
+        None
+    }
+}
+
+// Build a map from the items of a map literal, which alternate between keys and
+// values. As in Clojure, a key may only appear once in a literal.
+pub(crate) fn map_from_items(items: Vec<Expression>) -> Result<Expression, Error> {
+    if !items.len().is_multiple_of(2) {
+        return Err(Error::new(ErrorKind::UnbalancedEntries));
+    }
+    let mut map = BTreeMap::new();
+    let mut items = items.into_iter();
+    while let (Some(k), Some(v)) = (items.next(), items.next()) {
+        if map.contains_key(&k) {
+            return Err(Error::new(ErrorKind::DuplicateKey).with_message(&format!(
+                "The key {} appears more than once in a map literal.",
+                k
+            )));
+        }
+        map.insert(k, v);
+    }
+    Ok(Expression::Map(map))
+}
+
+// Build a set from the items of a set literal. As with map keys, an item may
+// only appear once in a literal.
+pub(crate) fn set_from_items(items: Vec<Expression>) -> Result<Expression, Error> {
+    let mut set = BTreeSet::new();
+    for item in items {
+        if set.contains(&item) {
+            return Err(Error::new(ErrorKind::DuplicateKey).with_message(&format!(
+                "The item {} appears more than once in a set literal.",
+                item
+            )));
+        }
+        set.insert(item);
+    }
+    Ok(Expression::Set(set))
+}
+
+// Read the items of a list, vector, map or set, up to and including the closing
+// delimiter, which must be `close`.
+fn read_items<'a>(
+    index: &LineIndex,
+    s: &'a str,
+    close: char,
+) -> Result<(&'a str, Vec<Expression>), Error> {
+    match tokenize(index, s)? {
+        (rem, Some(delim), items) if delim == close => Ok((rem, items)),
+        (_, Some(_), _) => Err(Error::new(ErrorKind::MismatchedDelimiter)),
+        (_, None, _) => Err(Error::new(ErrorKind::UnterminatedList)),
+    }
+}
+
+// Read every top-level form in a program, such as the contents of a script
+// file. Unlike parsing a single `Expression`, the forms need not be lists.
+pub fn parse_program(s: &str) -> Result<Vec<Expression>, Error> {
+    Ok(read_program(s)?
+        .into_iter()
+        .map(Expression::strip_spans)
+        .collect())
+}
+
+// Read every top-level form in a program, as `parse_program` does, but keep the
+// spans of the forms read, for use by the evaluator.
+pub(crate) fn read_program(s: &str) -> Result<Vec<Expression>, Error> {
+    match tokenize(&LineIndex::new(s), s)? {
+        (_, None, forms) => Ok(forms),
+        // A closing delimiter at the top level has nothing to close
+        (_, Some(_), _) => Err(Error::new(ErrorKind::MismatchedDelimiter)),
+    }
+}
+
+// Read items up to and including the next unmatched closing delimiter. Returns
+// the remaining input, the closing delimiter, and the items read. If the input
+// ends first, no closing delimiter is returned. Each list read is given its
+// span within the text indexed by `index`.
+fn tokenize<'a>(
+    index: &LineIndex,
+    s: &'a str,
+) -> Result<(&'a str, Option<char>, Vec<Expression>), Error> {
     let mut exprs = Vec::<Expression>::new();
     let at_delims = |c| {
         c == ' '
@@ -301,74 +954,638 @@ fn tokenize(s: &str) -> Result<(&str, Expression), Error> {
             || c == ')'
             || c == '['
             || c == ']'
+            || c == '{'
+            || c == '}'
+            || c == '"'
+            || c == ';'
     };
+
+    // Reader prefixes that have been read, but not yet applied to a form
+    let mut prefixes = Vec::new();
+
     let mut rem = s;
     while !rem.is_empty() {
-        let (before, after) = match rem.split_once(at_delims) {
-            Some((b, a)) => (b, a),
-            None => return Err(Error::UnterminatedList),
-        };
+        rem = skip_comments(rem)?;
+        if rem.is_empty() {
+            break;
+        }
+        // The final token of the input may not be followed by a delimiter
+        let (before, after) = rem.split_once(at_delims).unwrap_or((rem, ""));
         let tok = before.strip_suffix(at_delims).unwrap_or(before).trim();
-        let delim = rem
-            .strip_prefix(before)
-            .unwrap_or(" ")
-            .chars()
-            .next()
-            .unwrap();
+        let delim = rem[before.len()..].chars().next();
+        // The input from the delimiter onwards, and the span of the delimiter itself,
+        // which is used to locate errors in the form it opens
+        let open = &rem[before.len()..];
+        let open_span = Some(index.span(open, after));
+        let (mut tok_prefixes, tok) = strip_prefixes(tok);
+        prefixes.append(&mut tok_prefixes);
+
+        // A `#` immediately before `{` opens a set, rather than a map
+        let set_literal = tok == "#" && delim == Some('{');
 
         // Parse the atom
         if let Ok(x) = tok.parse::<i64>() {
-            exprs.push(Expression::Number(x));
+            exprs.extend(apply_prefixes(&mut prefixes, Expression::Number(x)));
+        } else if let Some(x) = parse_float(tok) {
+            exprs.extend(apply_prefixes(&mut prefixes, Expression::Float(x)));
         } else if let Ok(x) = tok.parse::<bool>() {
-            exprs.push(Expression::Bool(x));
-        } else if !tok.is_empty() {
+            exprs.extend(apply_prefixes(&mut prefixes, Expression::Bool(x)));
+        } else if !tok.is_empty() && !set_literal {
             // If the token is not empty, but we were unable to parse it into any other
-            // atomic types, it must be either the `nil` keyword or a symbol.
-            match tok {
-                "nil" => exprs.push(Expression::Nil),
-                _ => exprs.push(Expression::Symbol(tok.to_string())),
+            // atomic types, it must be either `nil`, a keyword (such as `:name`) or a
+            // symbol.
+            let expr = match tok {
+                "nil" => Expression::Nil,
+                _ => match tok.strip_prefix(':') {
+                    Some(name) if !name.is_empty() => Expression::Keyword(name.to_string()),
+                    _ => Expression::Symbol(tok.to_string()),
+                },
             };
+            exprs.extend(apply_prefixes(&mut prefixes, expr));
         }
 
-        // Handle lists and vectors
+        // Handle lists, vectors, maps and sets
         match delim {
-            '(' => {
-                let (after, expr) = tokenize(after)?;
-                if expr.is_list() {
-                    exprs.push(expr);
-                    rem = after;
-                } else {
-                    return Err(Error::MismatchedDelimiter);
-                }
+            Some('(') => {
+                let (after, items) =
+                    read_items(index, after, ')').map_err(|e| e.or_span(open_span))?;
+                let expr =
+                    Expression::Spanned(Box::new(Expression::List(items)), index.span(open, after));
+                exprs.extend(apply_prefixes(&mut prefixes, expr));
+                rem = after;
             }
-            '[' => {
-                let (after, expr) = tokenize(after)?;
-                if expr.is_vector() {
-                    exprs.push(expr);
-                    rem = after;
+            Some('[') => {
+                let (after, items) =
+                    read_items(index, after, ']').map_err(|e| e.or_span(open_span))?;
+                exprs.extend(apply_prefixes(&mut prefixes, Expression::Vector(items)));
+                rem = after;
+            }
+            Some('{') => {
+                let (after, items) =
+                    read_items(index, after, '}').map_err(|e| e.or_span(open_span))?;
+                let expr = if set_literal {
+                    set_from_items(items)
                 } else {
-                    return Err(Error::MismatchedDelimiter);
+                    map_from_items(items)
                 }
+                .map_err(|e| e.or_span(open_span))?;
+                exprs.extend(apply_prefixes(&mut prefixes, expr));
+                rem = after;
             }
-            ')' => {
-                return Ok((after, Expression::List(exprs)));
+            Some('"') => {
+                let (after, expr) = read_string(after).map_err(|e| e.or_span(open_span))?;
+                exprs.extend(apply_prefixes(&mut prefixes, expr));
+                rem = after;
+            }
+            // A reader prefix must be followed by the form it applies to
+            Some(')' | ']' | '}') if !prefixes.is_empty() => {
+                return Err(Error::new(ErrorKind::UnexpectedSymbol));
             }
-            ']' => {
-                return Ok((after, Expression::Vector(exprs)));
+            Some(')' | ']' | '}') => {
+                return Ok((after, delim, exprs));
+            }
+            // Line comments run to the end of the line
+            Some(';') => {
+                rem = after.split_once('\n').map_or("", |(_, rem)| rem);
             }
             _ => {
                 rem = after;
             }
         }
     }
-    Ok((rem, Expression::List(exprs)))
+    if !prefixes.is_empty() {
+        return Err(Error::new(ErrorKind::UnexpectedSymbol));
+    }
+    Ok((rem, None, exprs))
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{parse_program, read_program};
+    use crate::span::Span;
+    use crate::Environment;
+    use crate::ErrorKind;
+    use crate::Expression;
+    use crate::FromExpression;
+    use alloc::format;
+    use alloc::rc::Rc;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::cmp::Ordering;
+
+    fn sym(s: &str) -> Expression {
+        Expression::Symbol(s.to_string())
+    }
+
+    #[test]
+    fn reader_prefixes() {
+        assert_eq!(
+            "(f 'x)".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                Expression::List(vec![sym("quote"), sym("x")])
+            ]))
+        );
+        assert_eq!(
+            "(f '(1 2) `[a ~b ~@c])".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                Expression::List(vec![
+                    sym("quote"),
+                    Expression::List(vec![Expression::Number(1), Expression::Number(2)])
+                ]),
+                Expression::List(vec![
+                    sym("quasiquote"),
+                    Expression::Vector(vec![
+                        sym("a"),
+                        Expression::List(vec![sym("unquote"), sym("b")]),
+                        Expression::List(vec![sym("unquote-splicing"), sym("c")]),
+                    ])
+                ]),
+            ]))
+        );
+        assert_eq!(
+            "(f ''x)".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                Expression::List(vec![
+                    sym("quote"),
+                    Expression::List(vec![sym("quote"), sym("x")])
+                ])
+            ]))
+        );
+        assert_eq!(
+            "(f ')".parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedSymbol)
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let string = |s: &str| Expression::String(s.to_string());
+        assert_eq!(
+            "(f \"hi there\")".parse(),
+            Ok(Expression::List(vec![sym("f"), string("hi there")]))
+        );
+        assert_eq!(
+            "(f\"a\"\"b\"[\"(\"])".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                string("a"),
+                string("b"),
+                Expression::Vector(vec![string("(")])
+            ]))
+        );
+        assert_eq!(
+            r#"(f "a\nb\t\"c\"\\ \u{e9}\u{1F600}")"#.parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                string("a\nb\t\"c\"\\ \u{e9}\u{1F600}")
+            ]))
+        );
+        assert_eq!(
+            "(f 'x\"y\")".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                Expression::List(vec![sym("quote"), sym("x")]),
+                string("y")
+            ]))
+        );
+        assert_eq!(
+            r#"(f "\q")"#.parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidEscape)
+        );
+        assert_eq!(
+            r#"(f "\u{110000}")"#.parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidEscape)
+        );
+        assert_eq!(
+            r#"(f "\u{41")"#.parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidEscape)
+        );
+        assert_eq!(
+            "(f \"abc)".parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::UnterminatedString)
+        );
+    }
+
+    #[test]
+    fn program() {
+        assert_eq!(
+            parse_program("(def a 1) 42 :k\n\"s\" [a] ; done"),
+            Ok(vec![
+                Expression::List(vec![sym("def"), sym("a"), Expression::Number(1)]),
+                Expression::Number(42),
+                Expression::Keyword("k".to_string()),
+                Expression::String("s".to_string()),
+                Expression::Vector(vec![sym("a")]),
+            ])
+        );
+        assert_eq!(parse_program("x"), Ok(vec![sym("x")]));
+        assert_eq!(
+            parse_program("'x"),
+            Ok(vec![Expression::List(vec![sym("quote"), sym("x")])])
+        );
+        assert_eq!(parse_program(""), Ok(vec![]));
+        assert_eq!(parse_program("  ; nothing\n #| here |#"), Ok(vec![]));
+        assert_eq!(
+            parse_program("(a) (b").map_err(|e| e.kind()),
+            Err(ErrorKind::UnterminatedList)
+        );
+        assert_eq!(
+            parse_program("(a))").map_err(|e| e.kind()),
+            Err(ErrorKind::MismatchedDelimiter)
+        );
+        assert_eq!(
+            parse_program("(a) '").map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedSymbol)
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let forms = read_program("(f 1)\n  (g [(h)]\n     (é))").unwrap();
+        let span = |expr: &Expression| {
+            let span = expr.span().unwrap();
+            (
+                span.start.line,
+                span.start.column,
+                span.end.line,
+                span.end.column,
+            )
+        };
+        assert_eq!(span(&forms[0]), (1, 1, 1, 6));
+        assert_eq!(span(&forms[1]), (2, 3, 3, 10));
+        let items: Vec<Expression> = forms[1].clone().try_into().unwrap();
+        let inner: Vec<Expression> = items[1].clone().try_into().unwrap();
+        assert_eq!(span(&inner[0]), (2, 7, 2, 10));
+        assert_eq!(span(&items[2]), (3, 6, 3, 9));
+        assert_eq!(items[0].span(), None);
+
+        // Spans don't affect equality, and can be removed
+        assert_eq!(
+            forms[0],
+            Expression::List(vec![sym("f"), Expression::Number(1)])
+        );
+        assert_eq!(forms[0].clone().strip_spans().span(), None);
+        assert!(forms[0].is_list());
+
+        // Helpers and conversions look through spans
+        let spanned = |expr: Expression| Expression::Spanned(Box::new(expr), Span::default());
+        assert!(spanned(Expression::Vector(vec![])).is_vector());
+        assert!(spanned(sym("a")).is_symbol());
+        assert!(!spanned(Expression::Nil).is_truthy());
+        assert_eq!(spanned(Expression::Number(1)).try_into(), Ok(1i64));
+        assert_eq!(
+            spanned(Expression::String("a".to_string())).try_into(),
+            Ok("a".to_string())
+        );
+        assert_eq!(
+            Vec::<i64>::from_expression(spanned(Expression::Vector(vec![Expression::Number(2)]))),
+            Ok(vec![2])
+        );
+        assert_eq!(spanned(sym("a")).as_unspanned(), &sym("a"));
+
+        // The public parsers produce plain data
+        assert!(matches!(
+            "(a (b))".parse::<Expression>(),
+            Ok(Expression::List(items)) if matches!(items[1], Expression::List(_))
+        ));
+        assert!(matches!(
+            parse_program("(a) [(b)]").unwrap()[..],
+            [Expression::List(_), Expression::Vector(ref v)] if matches!(v[0], Expression::List(_))
+        ));
+
+        // Reader errors are located at the opening delimiter of the form
+        let err = parse_program("(a)\n(b [c)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::MismatchedDelimiter);
+        let span = err.span().unwrap();
+        assert_eq!((span.start.line, span.start.column), (2, 4));
+        let err = parse_program("(a\n \"b)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnterminatedString);
+        assert_eq!(err.span().unwrap().start.line, 2);
+    }
+
+    #[test]
+    fn comments() {
+        let expected = Ok(Expression::List(vec![
+            sym("f"),
+            Expression::Number(1),
+            Expression::Vector(vec![Expression::Number(2)]),
+        ]));
+        assert_eq!("(f 1 ; one\n [2]) ; trailing".parse(), expected);
+        assert_eq!("(f;(\n1;]\n[2])".parse(), expected);
+        assert_eq!(
+            "(f #| a (block\n ] comment |# 1 [2 #||#])".parse(),
+            expected
+        );
+        assert_eq!(
+            "(f \"; not a comment\")".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                Expression::String("; not a comment".to_string()),
+            ]))
+        );
+        assert_eq!(
+            "(f 1 ; )".parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::UnterminatedList)
+        );
+        assert_eq!(
+            "(f 1 #| ) |#".parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::UnterminatedList)
+        );
+        assert_eq!(
+            "(f 1 #| )".parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::UnterminatedComment)
+        );
+        assert_eq!(
+            "(f 1 ; ]\n ]".parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::MismatchedDelimiter)
+        );
+        assert_eq!(
+            "(f 1 [2 #| ) |# ))"
+                .parse::<Expression>()
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::MismatchedDelimiter)
+        );
+    }
+
+    #[test]
+    fn discard() {
+        let expected = Ok(Expression::List(vec![
+            sym("f"),
+            Expression::Number(1),
+            Expression::Vector(vec![Expression::Number(2)]),
+        ]));
+        assert_eq!("(f #_x 1 #_(a [b]) [#_ #{3} 2])".parse(), expected);
+        assert_eq!("(f #_ #_ a b 1 [2 #_\"s\"])".parse(), expected);
+        assert_eq!(
+            "(f '#_x y)".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                Expression::List(vec![sym("quote"), sym("y")])
+            ]))
+        );
+        assert_eq!(
+            "(f #_)".parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedSymbol)
+        );
+        assert_eq!(
+            "(f #_(1 2])".parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::MismatchedDelimiter)
+        );
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(
+            "(f 1.5 -2.0 1e-3 .5 ##Inf ##-Inf 1 inf)".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                Expression::Float(1.5),
+                Expression::Float(-2.0),
+                Expression::Float(0.001),
+                Expression::Float(0.5),
+                Expression::Float(f64::INFINITY),
+                Expression::Float(f64::NEG_INFINITY),
+                Expression::Number(1),
+                sym("inf"),
+            ]))
+        );
+        let nan = "(##NaN)".parse::<Expression>().unwrap();
+        assert_eq!(nan, Expression::List(vec![Expression::Float(f64::NAN)]));
+        assert_eq!(format!("{}", nan), "( ##NaN )");
+        let list = Expression::List(vec![
+            Expression::Float(1.0),
+            Expression::Float(-0.25),
+            Expression::Float(f64::NEG_INFINITY),
+        ]);
+        assert_eq!(format!("{}", list), "( 1.0 -0.25 ##-Inf )");
+        assert_eq!(format!("{}", list).parse(), Ok(list));
+    }
+
+    #[test]
+    fn float_ordering() {
+        let mut values = vec![
+            Expression::Float(f64::NAN),
+            Expression::Float(2.5),
+            Expression::Number(2),
+            Expression::Float(2.0),
+            Expression::Float(f64::NEG_INFINITY),
+            Expression::Number(3),
+        ];
+        values.sort();
+        assert_eq!(
+            values,
+            vec![
+                Expression::Float(f64::NEG_INFINITY),
+                Expression::Number(2),
+                Expression::Float(2.0),
+                Expression::Float(2.5),
+                Expression::Number(3),
+                Expression::Float(f64::NAN),
+            ]
+        );
+        assert_ne!(Expression::Number(2), Expression::Float(2.0));
+        assert_eq!(Expression::Float(0.0), Expression::Float(-0.0));
+    }
+
+    #[test]
+    fn maps() {
+        let map =
+            |items: &[(Expression, Expression)]| Expression::Map(items.iter().cloned().collect());
+        assert_eq!(
+            "(f {a 1 \"b\" [2]} {})".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                map(&[
+                    (sym("a"), Expression::Number(1)),
+                    (
+                        Expression::String("b".to_string()),
+                        Expression::Vector(vec![Expression::Number(2)])
+                    ),
+                ]),
+                map(&[]),
+            ]))
+        );
+        assert_eq!(
+            "(f {a {b 1}})".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                map(&[(sym("a"), map(&[(sym("b"), Expression::Number(1))]))]),
+            ]))
+        );
+        assert_eq!(
+            "(f {a 1 b})".parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::UnbalancedEntries)
+        );
+        let err = "(f {1 2 1 3})".parse::<Expression>().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DuplicateKey);
+        assert_eq!(
+            format!("{}", err),
+            "Duplicate key. The key 1 appears more than once in a map literal. (at line 1, column 4)"
+        );
+        assert_eq!(
+            "(f {a 1])".parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::MismatchedDelimiter)
+        );
+        let m = map(&[
+            (Expression::Number(2), sym("two")),
+            (Expression::Nil, Expression::Bool(true)),
+        ]);
+        assert_eq!(format!("{}", m), "{ nil true 2 two }");
+        let list = Expression::List(vec![sym("f"), m]);
+        assert_eq!(format!("{}", list).parse(), Ok(list));
+    }
+
     #[test]
-    fn it_works() {
-        let result = 2 + 2;
-        assert_eq!(result, 4);
+    fn sets() {
+        let set = |items: &[Expression]| Expression::Set(items.iter().cloned().collect());
+        assert_eq!(
+            "(f #{1 2} #{} '#{a})".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                set(&[Expression::Number(1), Expression::Number(2)]),
+                set(&[]),
+                Expression::List(vec![sym("quote"), set(&[sym("a")])]),
+            ]))
+        );
+        assert_eq!(
+            "(f #{3 1 2})".parse::<Expression>(),
+            "(f #{2 3 1})".parse::<Expression>()
+        );
+        assert_eq!(
+            "(f #{1])".parse::<Expression>().map_err(|e| e.kind()),
+            Err(ErrorKind::MismatchedDelimiter)
+        );
+        let err = "(f #{1 2 1})".parse::<Expression>().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DuplicateKey);
+        assert_eq!(
+            format!("{}", err),
+            "Duplicate key. The item 1 appears more than once in a set literal. (at line 1, column 5)"
+        );
+        assert_eq!(
+            "(f # {1 2})".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                sym("#"),
+                Expression::Map(
+                    [(Expression::Number(1), Expression::Number(2))]
+                        .into_iter()
+                        .collect()
+                ),
+            ]))
+        );
+        let s = set(&[Expression::Number(2), Expression::Nil]);
+        assert_eq!(format!("{}", s), "#{ nil 2 }");
+        let list = Expression::List(vec![sym("f"), s]);
+        assert_eq!(format!("{}", list).parse(), Ok(list));
+    }
+
+    #[test]
+    fn keywords() {
+        let kw = Expression::keyword;
+        assert_eq!(
+            "(f :a {:b 1} :)".parse(),
+            Ok(Expression::List(vec![
+                sym("f"),
+                kw("a"),
+                Expression::Map([(kw("b"), Expression::Number(1))].into_iter().collect()),
+                sym(":"),
+            ]))
+        );
+        assert_ne!(kw("a"), sym("a"));
+        assert_eq!(format!("{}", kw("a")), ":a");
+        let list = Expression::List(vec![sym("f"), kw("a-b?")]);
+        assert_eq!(format!("{}", list).parse(), Ok(list));
+    }
+
+    #[test]
+    fn mixed_ordering() {
+        let mut values = vec![
+            Expression::Vector(vec![Expression::Number(1), Expression::Number(2)]),
+            Expression::String("a".to_string()),
+            Expression::Vector(vec![Expression::Number(1)]),
+            Expression::Number(1),
+            Expression::Nil,
+            sym("a"),
+            Expression::Bool(false),
+        ];
+        values.sort();
+        assert_eq!(
+            values,
+            vec![
+                Expression::Nil,
+                Expression::Bool(false),
+                Expression::Number(1),
+                Expression::String("a".to_string()),
+                sym("a"),
+                Expression::Vector(vec![Expression::Number(1)]),
+                Expression::Vector(vec![Expression::Number(1), Expression::Number(2)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn string_round_trip() {
+        let expr = Expression::String("a \"quoted\"\n\\ \u{7}é".to_string());
+        assert_eq!(format!("{}", expr), r#""a \"quoted\"\n\\ \u{7}é""#);
+        assert_eq!(format!("{:?}", expr), format!("{}", expr));
+        let list = Expression::List(vec![sym("f"), expr]);
+        assert_eq!(format!("{}", list).parse(), Ok(list));
+    }
+
+    #[test]
+    fn userdata() {
+        struct Handle(u8);
+
+        let a = Expression::userdata("handle", Handle(1));
+        let b = Expression::userdata("handle", Handle(1));
+        assert_eq!(a, a.clone());
+        assert_ne!(a, b);
+        assert_eq!(a.type_name(), "handle");
+        assert_eq!(format!("{}", a), "#<handle>");
+        assert_eq!(a.downcast_ref::<Handle>().map(|h| h.0), Some(1));
+        assert!(a.downcast_ref::<u8>().is_none());
+        assert!(Expression::Nil.downcast::<Handle>().is_none());
+
+        // Names do not affect identity
+        let Expression::Userdata(_, value) = a.clone() else {
+            unreachable!()
+        };
+        let renamed = Expression::Userdata("other", value);
+        assert_eq!(a, renamed);
+        assert_eq!(a.cmp(&renamed), Ordering::Equal);
+        assert_eq!(a.cmp(&b), b.cmp(&a).reverse());
+
+        let mut env = Environment::new();
+        env.load_default_builtins().unwrap();
+        env.register("open", |id: i64| {
+            Ok(Expression::userdata("handle", Handle(id as u8)))
+        })
+        .unwrap();
+        env.register("id", |h: Rc<Handle>| Ok(h.0 as i64)).unwrap();
+
+        // Userdata may be stored in collections, and passed between functions
+        assert_eq!(
+            env.parse_eval(
+                "(let [hs [(open 1) (open 2)]
+                       f (fn [h] (id h))]
+                   (map f (conj hs (first hs))))"
+            ),
+            env.parse_eval("(list 1 2 1)")
+        );
+        assert_eq!(
+            env.parse_eval("(let [h (open 1)] [(== h h) (== h (open 1)) (count (set [h h]))])"),
+            env.parse_eval("(do [true false 1])")
+        );
+        let err = env.parse_eval("(+ 1 (open 1))").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ImpossibleConversion);
+        assert_eq!(err.context.unwrap().value_type, Some("handle"));
+        assert_eq!(
+            env.parse_eval("(id 1)").map_err(|e| e.kind()),
+            Err(ErrorKind::ImpossibleConversion)
+        );
     }
 }
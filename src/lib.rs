@@ -1,14 +1,18 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 #![feature(iterator_try_collect)]
-#![feature(iterator_try_reduce)]
 
 extern crate alloc;
 
 pub mod builtins;
+pub mod convert;
+pub mod destructure;
 pub mod environment;
 pub mod error;
 pub mod expression;
+pub mod span;
 
+pub use convert::{FromExpression, IntoArgs, IntoExpression, NativeFunction};
 pub use environment::Environment;
-pub use error::Error;
-pub use expression::Expression;
+pub use error::{Arity, Error, ErrorContext, ErrorKind, TraceFrame};
+pub use expression::{parse_program, Expression};
+pub use span::Span;
@@ -0,0 +1,105 @@
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::fmt;
+
+// A position in source text. Lines and columns count from 1, and columns count
+// characters rather than bytes. `offset` is the byte offset from the start of
+// the source.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+// The region of source text a form was read from, from the start of its first
+// character up to (but not including) `end`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.start.line, self.start.column)
+    }
+}
+
+// The start of each line of some source text, used to convert byte offsets
+// into positions.
+pub(crate) struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub(crate) fn new(text: &'a str) -> Self {
+        let line_starts = core::iter::once(0)
+            .chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+        LineIndex { text, line_starts }
+    }
+
+    // The position of the start of `rem`, which must be a suffix of the indexed
+    // text.
+    pub(crate) fn position(&self, rem: &str) -> Position {
+        let offset = self.text.len() - rem.len();
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let column = self.text[self.line_starts[line - 1]..offset]
+            .chars()
+            .count()
+            + 1;
+        Position {
+            offset,
+            line,
+            column,
+        }
+    }
+
+    // The span from the start of `start` to the start of `end`, both of which must
+    // be suffixes of the indexed text.
+    pub(crate) fn span(&self, start: &str, end: &str) -> Span {
+        Span {
+            start: self.position(start),
+            end: self.position(end),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineIndex, Position};
+
+    #[test]
+    fn positions() {
+        let text = "(a\n  (é b)\n)";
+        let index = LineIndex::new(text);
+        assert_eq!(
+            index.position(text),
+            Position {
+                offset: 0,
+                line: 1,
+                column: 1
+            }
+        );
+        assert_eq!(
+            index.position(&text[6..]),
+            Position {
+                offset: 6,
+                line: 2,
+                column: 4
+            }
+        );
+        assert_eq!(
+            index.position(&text[12..]),
+            Position {
+                offset: 12,
+                line: 3,
+                column: 1
+            }
+        );
+        assert_eq!(index.position("").line, 3);
+    }
+}